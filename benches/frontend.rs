@@ -0,0 +1,63 @@
+//! Lexes and parses generated scripts of doubling size and reports the
+//! time per byte, which should stay roughly flat if both are linear.
+//!
+//! Run with `cargo bench --bench frontend`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use sun::{tokenize, Parser};
+
+const CHUNK: &str = r#"
+// counts down and builds an object
+let counter = 1000;
+const label = "count\tdown";
+/* block /* nested */ comment */
+while (counter > 0) {
+    counter = counter - 1;
+}
+let point = { x: 1.5, y: -2, tags: ["a", 'b'], nested: { ok: true } };
+fn add(a, b) { return a + b * 2 % 3; }
+if (add(point.x, point.y) >= 10 && !false || nil == nil) { println(label); }
+"#;
+
+fn source_of_size(bytes: usize) -> String {
+    CHUNK.repeat(bytes / CHUNK.len() + 1)
+}
+
+/// Best of a few runs to smooth out noise.
+fn best_of(runs: usize, mut f: impl FnMut()) -> Duration {
+    (0..runs)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    println!("{:>10} {:>10} {:>12} {:>10} {:>12} {:>10}", "bytes", "tokens", "lex", "ns/byte", "parse", "ns/byte");
+
+    let mut per_byte = Vec::new();
+    for megabytes in [1, 2, 4, 8] {
+        let source = source_of_size(megabytes << 20);
+        let tokens = tokenize(&source, "<bench>").expect("benchmark source should lex").len();
+
+        let lex = best_of(3, || {
+            black_box(tokenize(black_box(&source), "<bench>").unwrap().len());
+        });
+        let parse = best_of(3, || {
+            black_box(Parser::with_file("<bench>").produce_ast(black_box(&source)).unwrap().body.len());
+        });
+
+        let lex_per_byte = lex.as_nanos() as f64 / source.len() as f64;
+        let parse_per_byte = parse.as_nanos() as f64 / source.len() as f64;
+        per_byte.push((lex_per_byte, parse_per_byte));
+        println!("{:>10} {:>10} {:>12.2?} {:>10.2} {:>12.2?} {:>10.2}", source.len(), tokens, lex, lex_per_byte, parse, parse_per_byte);
+    }
+
+    let (first, last) = (per_byte[0], per_byte[per_byte.len() - 1]);
+    println!("\ncost per byte at 8 MiB vs 1 MiB (1.0 = linear): lex {:.2}x, parse {:.2}x", last.0 / first.0, last.1 / first.1);
+}
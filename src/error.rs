@@ -0,0 +1,78 @@
+use std::fmt;
+
+use crate::lexer::{LexError, Span};
+
+/// Error produced while lexing, parsing or evaluating Sun code.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SunError {
+    Lex { span: Span, message: String },
+    Parse { span: Span, message: String },
+    Runtime { span: Span, message: String },
+    /// A script file could not be read; `span` points at the file itself.
+    Io { span: Span, message: String },
+}
+
+impl SunError {
+    pub fn lex(span: &Span, message: impl Into<String>) -> Self {
+        SunError::Lex { span: span.clone(), message: message.into() }
+    }
+
+    pub fn parse(span: &Span, message: impl Into<String>) -> Self {
+        SunError::Parse { span: span.clone(), message: message.into() }
+    }
+
+    pub fn runtime(span: &Span, message: impl Into<String>) -> Self {
+        SunError::Runtime { span: span.clone(), message: message.into() }
+    }
+
+    pub fn io(path: &str, message: impl Into<String>) -> Self {
+        let span = Span { file: path.into(), line: 1, column: 1, ..Span::default() };
+        SunError::Io { span, message: message.into() }
+    }
+
+    /// Runtime error without a location yet, for native functions to
+    /// return; the call site's span is filled in by the interpreter.
+    pub fn native(message: impl Into<String>) -> Self {
+        SunError::Runtime { span: Span::default(), message: message.into() }
+    }
+
+    /// Replaces the span of an error that was raised without one.
+    pub fn or_span(self, span: &Span) -> Self {
+        match self {
+            SunError::Runtime { span: missing, message } if missing == Span::default() => SunError::runtime(span, message),
+            err => err,
+        }
+    }
+
+    pub fn span(&self) -> &Span {
+        match self {
+            SunError::Lex { span, .. } | SunError::Parse { span, .. } | SunError::Runtime { span, .. } | SunError::Io { span, .. } => span,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            SunError::Lex { message, .. } | SunError::Parse { message, .. } | SunError::Runtime { message, .. } | SunError::Io { message, .. } => message,
+        }
+    }
+}
+
+impl From<LexError> for SunError {
+    fn from(err: LexError) -> Self {
+        SunError::Lex { span: err.span, message: err.message }
+    }
+}
+
+impl fmt::Display for SunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stage = match self {
+            SunError::Lex { .. } => "Lexer",
+            SunError::Parse { .. } => "Parser",
+            SunError::Runtime { .. } => "Runtime",
+            SunError::Io { .. } => return write!(f, "IO Error: {}", self.message()),
+        };
+        write!(f, "{} Error at {}:\n{}", stage, self.span(), self.message())
+    }
+}
+
+impl std::error::Error for SunError {}
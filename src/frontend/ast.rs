@@ -1,262 +1,218 @@
-use std::{collections::HashMap, fmt, iter::Map};
+//! Syntax tree produced by [`Parser`](crate::Parser).
+//!
+//! ```
+//! use sun::ast::{Expr, Stmt};
+//!
+//! let program = sun::Parser::new().produce_ast("let x = 1; x + 2;").unwrap();
+//! match &program.body[1] {
+//!     Stmt::Expr(Expr::Binary(binary)) => assert_eq!(binary.operator, "+"),
+//!     other => panic!("unexpected statement {:?}", other),
+//! }
+//! ```
+
+use std::rc::Rc;
+
+use crate::lexer::Span;
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum NodeType {
-    // Statements
-    Program,
-    VarDeclarationStmt,
-    // Expressions
-    AssignmentExpr,
-    // Literals
-    Property,
-    ObjectLiteral,
-    NumericLiteral,
-    StringLiteral,
-    Identifier,
-    BinaryExpr,
+pub struct Program {
+    pub span: Span,
+    pub body: Vec<Stmt>,
 }
 
-pub trait Stmt: fmt::Debug {
-    fn get_kind(&self) -> NodeType;
-    fn as_numeric_literal(&self) -> Option<&NumericLiteral> {
-        None
-    }
-    fn as_binary_expr(&self) -> Option<&BinaryExpr> {
-        None
-    }
-    fn as_program(&self) -> Option<&Program> {
-        None
-    }
-    fn as_identifier(&self) -> Option<&Identifier> {
-        None
-    }
-    fn as_var_declaration(&self) -> Option<&VarDeclaration> {
-        None
-    }
-    fn as_assignment_expr(&self) -> Option<&AssignmentExpr> {
-        None
-    }
-    fn as_property(&self) -> Option<&Property> {
-        None
-    }
-    fn as_object_literal(&self) -> Option<&ObjectLiteral> {
-        None
-    }
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    VarDeclaration(VarDeclaration),
+    If(IfStmt),
+    While(WhileStmt),
+    Block(BlockStmt),
+    FunctionDeclaration(Function),
+    Return(ReturnStmt),
+    Expr(Expr),
+    Error(ErrorStmt),
 }
 
-pub trait Expr: Stmt {
-    fn into_boxed_stmt(self: Box<Self>) -> Box<dyn Stmt>;
-    fn as_stmt(&self) -> &dyn Stmt;
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Assignment(AssignmentExpr),
+    Binary(BinaryExpr),
+    Unary(UnaryExpr),
+    Call(CallExpr),
+    Member(MemberExpr),
+    Function(Function),
+    Identifier(Identifier),
+    NumericLiteral(NumericLiteral),
+    StringLiteral(StringLiteral),
+    ObjectLiteral(ObjectLiteral),
+    ArrayLiteral(ArrayLiteral),
+    Error(ErrorExpr),
+}
+
+impl Stmt {
+    pub fn span(&self) -> &Span {
+        match self {
+            Stmt::VarDeclaration(node) => &node.span,
+            Stmt::If(node) => &node.span,
+            Stmt::While(node) => &node.span,
+            Stmt::Block(node) => &node.span,
+            Stmt::FunctionDeclaration(node) => &node.span,
+            Stmt::Return(node) => &node.span,
+            Stmt::Expr(expr) => expr.span(),
+            Stmt::Error(node) => &node.span,
+        }
+    }
+}
+
+impl Expr {
+    pub fn span(&self) -> &Span {
+        match self {
+            Expr::Assignment(node) => &node.span,
+            Expr::Binary(node) => &node.span,
+            Expr::Unary(node) => &node.span,
+            Expr::Call(node) => &node.span,
+            Expr::Member(node) => &node.span,
+            Expr::Function(node) => &node.span,
+            Expr::Identifier(node) => &node.span,
+            Expr::NumericLiteral(node) => &node.span,
+            Expr::StringLiteral(node) => &node.span,
+            Expr::ObjectLiteral(node) => &node.span,
+            Expr::ArrayLiteral(node) => &node.span,
+            Expr::Error(node) => &node.span,
+        }
+    }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct VarDeclaration {
-    pub kind: NodeType,
+    pub span: Span,
     pub constant: bool,
     pub identifier: String,
-    pub value: Option<Box<dyn Expr>>,
+    pub value: Option<Expr>,
 }
 
-impl fmt::Debug for VarDeclaration {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{{kind: {:?}, constant: {:?}, iden:ifier: {:?}, value: {:#?}}}", self.kind, self.constant, self.identifier, self.value)
-    }
+#[derive(Debug, Clone, PartialEq)]
+pub struct IfStmt {
+    pub span: Span,
+    pub condition: Expr,
+    pub then_branch: Box<Stmt>,
+    pub else_branch: Option<Box<Stmt>>,
 }
 
-pub struct AssignmentExpr {
-    pub kind: NodeType,
-    pub assgine: Box<dyn Expr>,
-    pub value: Box<dyn Expr>
+#[derive(Debug, Clone, PartialEq)]
+pub struct WhileStmt {
+    pub span: Span,
+    pub condition: Expr,
+    pub body: Box<Stmt>,
 }
 
-impl fmt::Debug for AssignmentExpr {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{{\n\tkind: {:?}\n\tassgine: {:#?}\n\tvalue: {:#?}\n}}", self.kind, self.assgine, self.value)
-    }
+/// `{ ... }` statement list evaluated in its own child scope.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockStmt {
+    pub span: Span,
+    pub body: Vec<Stmt>,
 }
 
-pub struct Program {
-    pub kind: NodeType,
-    pub body: Vec<Box<dyn Stmt>>,
+/// `fn name(params) { body }`, either as a declaration or as an
+/// anonymous function expression (`name` is `None`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub span: Span,
+    pub name: Option<String>,
+    pub parameters: Vec<String>,
+    pub body: Rc<Vec<Stmt>>,
 }
 
-impl fmt::Debug for Program {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{{\nkind: {:?},\nbody: {:#?} }}\n", self.kind, self.body)
-    }
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReturnStmt {
+    pub span: Span,
+    pub value: Option<Expr>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssignmentExpr {
+    pub span: Span,
+    pub assgine: Box<Expr>,
+    pub value: Box<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct BinaryExpr {
-    pub kind: NodeType,
-    pub left: Box<dyn Expr>,
-    pub right: Box<dyn Expr>,
+    pub span: Span,
+    pub left: Box<Expr>,
+    pub right: Box<Expr>,
     pub operator: String,
 }
 
-impl fmt::Debug for BinaryExpr {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{{\n\tkind: {:?}\n\tleft: {:#?}\n\tright: {:#?}\n\toperator: {:?} \n}}\n",
-            self.kind, self.left, self.right, self.operator
-        )
-    }
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnaryExpr {
+    pub span: Span,
+    pub operator: String,
+    pub argument: Box<Expr>,
 }
 
-pub struct Identifier {
-    pub kind: NodeType,
-    pub symbol: String,
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallExpr {
+    pub span: Span,
+    pub callee: Box<Expr>,
+    pub arguments: Vec<Expr>,
 }
 
-impl fmt::Debug for Identifier {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{{\tkind: {:?},\tsymbol: {:?}}}\n", self.kind, self.symbol)
-    }
+/// `object.property` or, when `computed`, `object[property]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberExpr {
+    pub span: Span,
+    pub object: Box<Expr>,
+    pub property: Box<Expr>,
+    pub computed: bool,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identifier {
+    pub span: Span,
+    pub symbol: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct NumericLiteral {
-    pub kind: NodeType,
+    pub span: Span,
     pub value: f64,
 }
 
-impl fmt::Debug for NumericLiteral {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{{\tkind: {:?},\tvalue: {:?} }}\n", self.kind, self.value)
-    }
+#[derive(Debug, Clone, PartialEq)]
+pub struct StringLiteral {
+    pub span: Span,
+    pub value: String,
 }
 
+/// `key: value`, or the shorthand `key` (`value` is `None`).
+#[derive(Debug, Clone, PartialEq)]
 pub struct Property {
-    pub kind: NodeType,
+    pub span: Span,
     pub key: String,
-    pub value: Option<Box<dyn Expr>>
-}
-
-impl fmt::Debug for Property {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{{\nkind: {:?}\nKey: {},\nValue: {:?} }}", self.kind, self.key, self.value)
-    }
+    pub value: Option<Expr>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct ObjectLiteral {
-    pub kind: NodeType,
-    pub value: HashMap<String, Property>,
+    pub span: Span,
+    pub properties: Vec<Property>,
 }
 
-impl fmt::Debug for ObjectLiteral {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{{\nkind: {:?}, \nvalue: {:?}}}", self.kind, self.value)
-    }
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArrayLiteral {
+    pub span: Span,
+    pub elements: Vec<Expr>,
 }
 
-impl Stmt for AssignmentExpr {
-    fn get_kind(&self) -> NodeType {
-        self.kind.clone()
-    }
-    fn as_assignment_expr(&self) -> Option<&AssignmentExpr> {
-        Some(self)
-    }
-}
-impl Stmt for VarDeclaration {
-    fn get_kind(&self) -> NodeType {
-        self.kind.clone()
-    }
-    fn as_var_declaration(&self) -> Option<&VarDeclaration> {
-        Some(self)
-    }
-}
-impl Stmt for Program {
-    fn get_kind(&self) -> NodeType {
-        self.kind.clone()
-    }
-    fn as_program(&self) -> Option<&Program> {
-        Some(self)
-    }
-}
-impl Stmt for BinaryExpr {
-    fn get_kind(&self) -> NodeType {
-        self.kind.clone()
-    }
-    fn as_binary_expr(&self) -> Option<&BinaryExpr> {
-        Some(self)
-    }
-}
-impl Stmt for Identifier {
-    fn get_kind(&self) -> NodeType {
-        self.kind.clone()
-    }
-    fn as_identifier(&self) -> Option<&Identifier> {
-        Some(self)
-    }
-}
-impl Stmt for NumericLiteral {
-    fn get_kind(&self) -> NodeType {
-        self.kind.clone()
-    }
-    fn as_numeric_literal(&self) -> Option<&NumericLiteral> {
-        Some(self)
-    }
-}
-impl Stmt for ObjectLiteral {
-    fn get_kind(&self) -> NodeType {
-        self.kind.clone()
-    }
-    fn as_object_literal(&self) -> Option<&ObjectLiteral> {
-        Some(self)
-    }
-}
-impl Stmt for Property {
-    fn get_kind(&self) -> NodeType {
-        self.kind.clone()
-    }
-    fn as_property(&self) -> Option<&Property> {
-        Some(self)
-    }
+/// Statement the parser could not make sense of; the error itself is
+/// reported separately.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorStmt {
+    pub span: Span,
 }
 
-impl Expr for AssignmentExpr {
-    fn into_boxed_stmt(self: Box<Self>) -> Box<dyn Stmt> {
-        self
-    }
-    fn as_stmt(&self) -> &dyn Stmt {
-        self
-    }
-}
-impl Expr for BinaryExpr {
-    fn into_boxed_stmt(self: Box<Self>) -> Box<dyn Stmt> {
-        self
-    }
-    fn as_stmt(&self) -> &dyn Stmt {
-        self
-    }
-}
-impl Expr for Identifier {
-    fn into_boxed_stmt(self: Box<Self>) -> Box<dyn Stmt> {
-        self
-    }
-    fn as_stmt(&self) -> &dyn Stmt {
-        self
-    }
-}
-impl Expr for NumericLiteral {
-    fn into_boxed_stmt(self: Box<Self>) -> Box<dyn Stmt> {
-        self
-    }
-    fn as_stmt(&self) -> &dyn Stmt {
-        self
-    }
-}
-impl Expr for ObjectLiteral {
-    fn into_boxed_stmt(self: Box<Self>) -> Box<dyn Stmt> {
-        self
-    }
-    fn as_stmt(&self) -> &dyn Stmt {
-        self
-    }
-}
-impl Expr for Property {
-    fn into_boxed_stmt(self: Box<Self>) -> Box<dyn Stmt> {
-        self
-    }
-    fn as_stmt(&self) -> &dyn Stmt {
-        self
-    }
+/// Placeholder for input the lexer rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorExpr {
+    pub span: Span,
 }
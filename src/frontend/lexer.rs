@@ -1,4 +1,6 @@
-use std::{clone, vec::Vec, collections::HashMap};
+use std::{borrow::Cow, fmt, rc::Rc};
+
+use crate::error::SunError;
 
 #[derive(Clone, PartialEq, Debug)]
 pub enum TokenType {
@@ -7,122 +9,606 @@ pub enum TokenType {
     String,
     Identifier,
     // Operators
-    Comma, Colon,
+    Comma, Colon, Dot,
     Equals, Semicolon,
     BinaryOperator,
+    Bang, // !
     OpenParen, CloseParen, // ()
     OpenBrace, CloseBrace, // {}
     OpenBracket, CloseBracket, // []
     // Keywords
     Let, 
     Const,
+    If,
+    Else,
+    While,
+    Fn,
+    Return,
+    Typeof,
     // NaTT
     Nil,
+    // Stands in for input the lexer rejected
+    Error,
     EOF
 }
 
-#[derive(Debug)]
-pub struct Token {
-    pub value: String,
-    pub type_: TokenType
+/// Location of a token or node in the source: byte offsets plus the
+/// line and column (both 1-based) where it starts.
+#[derive(Clone, PartialEq, Default)]
+pub struct Span {
+    pub file: Rc<str>,
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
 }
 
-impl Token {
-    pub fn new(value: String, type_: TokenType) -> Self {
+impl Span {
+    /// Span covering from the start of `self` to the end of `other`.
+    pub fn to(&self, other: &Span) -> Span {
+        Span { end: other.end, ..self.clone() }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+impl fmt::Debug for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}@{}:{}", self.start, self.end, self.line, self.column)
+    }
+}
+
+/// A token. `value` borrows from the source except for string literals
+/// containing escape sequences, which have to be decoded.
+#[derive(Debug, Clone)]
+pub struct Token<'a> {
+    pub value: Cow<'a, str>,
+    pub type_: TokenType,
+    pub span: Span,
+}
+
+impl<'a> Token<'a> {
+    pub fn new(value: impl Into<Cow<'a, str>>, type_: TokenType, span: Span) -> Self {
         Self {
-            value: value,
-            type_: type_
+            value: value.into(),
+            type_,
+            span
         }
     }
 }
 
-fn token(value: &str, type_: TokenType) -> Token {
-    Token { value: value.to_string(), type_ }
+/// Error produced while lexing; converts into `SunError::Lex`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexError {
+    pub span: Span,
+    pub message: String,
 }
 
-fn isalpha(src: &str) -> bool {
-    src.to_uppercase() != src.to_lowercase()
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Lexer Error at {}:\n{}", self.span, self.message)
+    }
 }
 
-fn isint(str: &str) -> bool {
-    str.chars().all(|c| c.is_ascii_digit())
+impl std::error::Error for LexError {}
+
+#[derive(Clone, Copy)]
+struct Position {
+    offset: usize,
+    line: usize,
+    column: usize,
 }
 
-fn isnumber(str: &str) -> bool {
-    str.parse::<i64>().is_ok() || str.parse::<f64>().is_ok()
+/// Cursor over the source text. Peeking and advancing are O(1), so
+/// lexing a file is linear in its length.
+struct Cursor<'a> {
+    source: &'a str,
+    file: Rc<str>,
+    pos: Position,
 }
 
-fn keyword(str: String) -> TokenType {
-    if str == "let" {
-        return TokenType::Let;
-    } else if str == "const" {
-        return  TokenType::Const;
+impl<'a> Cursor<'a> {
+    fn new(source: &'a str, file: &str) -> Self {
+        Self {
+            source,
+            file: file.into(),
+            pos: Position { offset: 0, line: 1, column: 1 },
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.pos.offset..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn peek_next(&self) -> Option<char> {
+        let mut chars = self.rest().chars();
+        chars.next();
+        chars.next()
+    }
+
+    fn eat(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos.offset += c.len_utf8();
+        if c == '\n' {
+            self.pos.line += 1;
+            self.pos.column = 1;
+        } else {
+            self.pos.column += 1;
+        }
+        Some(c)
+    }
+
+    fn eat_while(&mut self, predicate: impl Fn(char) -> bool) {
+        while self.peek().is_some_and(&predicate) {
+            self.eat();
+        }
+    }
+
+    fn slice_from(&self, start: Position) -> &'a str {
+        &self.source[start.offset..self.pos.offset]
+    }
+
+    fn span_from(&self, start: Position) -> Span {
+        Span {
+            file: self.file.clone(),
+            start: start.offset,
+            end: self.pos.offset,
+            line: start.line,
+            column: start.column,
+        }
+    }
+}
+
+/// Identifiers follow Unicode's XID_Start / XID_Continue, plus `_` and `$`.
+fn is_ident_start(c: char) -> bool {
+    c == '_' || c == '$' || unicode_ident::is_xid_start(c)
+}
+
+fn is_ident_continue(c: char) -> bool {
+    c == '$' || unicode_ident::is_xid_continue(c)
+}
+
+fn keyword(str: &str) -> TokenType {
+    match str {
+        "let" => TokenType::Let,
+        "const" => TokenType::Const,
+        "if" => TokenType::If,
+        "else" => TokenType::Else,
+        "while" => TokenType::While,
+        "fn" => TokenType::Fn,
+        "return" => TokenType::Return,
+        "typeof" => TokenType::Typeof,
+        _ => TokenType::Identifier,
     }
-    TokenType::Nil
 }
 
-fn isskipabble(str: &str) -> bool {
-    return str == " " || str == "\n" || str == "\t" || str == "\r"
+fn isskipabble(c: char) -> bool {
+    c == ' ' || c == '\n' || c == '\t' || c == '\r'
 }
 
-pub fn tokenize(source_code: &str) -> Vec<Token> {
-    let mut tokens = Vec::new();
-    let mut src: Vec<char> = source_code.chars().collect();
-    while !src.is_empty() {
-        if src[0] == '(' {
-            tokens.push(token(&src.remove(0).to_string(), TokenType::OpenParen));
-        } else if src[0] == ')' {
-            tokens.push(token(&src.remove(0).to_string(), TokenType::CloseParen));
+/// Lazily produces the tokens of a source file, ending with a single
+/// `EOF` token. After an error it skips the offending input and carries on.
+pub struct Lexer<'a> {
+    src: Cursor<'a>,
+    finished: bool,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(source_code: &'a str, file: &str) -> Self {
+        Lexer { src: Cursor::new(source_code, file), finished: false }
+    }
+
+    fn token(&self, value: impl Into<Cow<'a, str>>, type_: TokenType, start: Position) -> Token<'a> {
+        Token::new(value, type_, self.src.span_from(start))
+    }
+
+    fn error(&self, start: Position, message: impl Into<String>) -> LexError {
+        LexError { span: self.src.span_from(start), message: message.into() }
+    }
+
+    fn escape(&mut self, start: Position) -> Result<char, LexError> {
+        let c = match self.src.eat() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some('"') => '"',
+            Some('\'') => '\'',
+            Some('\\') => '\\',
+            Some('u') => {
+                if self.src.peek() != Some('{') {
+                    return Err(self.error(self.src.pos, "Expected '{' following \\u in unicode escape."));
+                }
+                self.src.eat();
+                let hex_start = self.src.pos;
+                self.src.eat_while(|c| c.is_ascii_hexdigit());
+                let hex = self.src.slice_from(hex_start);
+                if self.src.peek() != Some('}') {
+                    return Err(self.error(self.src.pos, "Unicode escape missing closing '}'."));
+                }
+                self.src.eat();
+                match u32::from_str_radix(hex, 16).ok().and_then(char::from_u32) {
+                    Some(c) => c,
+                    None => return Err(self.error(start, format!("Invalid unicode escape: \\u{{{}}}", hex))),
+                }
+            }
+            Some(other) => return Err(self.error(start, format!("Unknown escape sequence: \\{}", other))),
+            None => return Err(self.error(start, "Unterminated string literal.")),
+        };
+        Ok(c)
+    }
+
+    fn string(&mut self, start: Position) -> Result<Token<'a>, LexError> {
+        let quote = self.src.eat();
+        let content_start = self.src.pos;
+        // Only allocated once an escape sequence is found
+        let mut decoded: Option<String> = None;
+        loop {
+            let before = self.src.pos;
+            match self.src.eat() {
+                None => return Err(self.error(start, "Unterminated string literal.")),
+                Some(c) if Some(c) == quote => {
+                    let value = match decoded {
+                        Some(decoded) => Cow::Owned(decoded),
+                        None => Cow::Borrowed(&self.src.source[content_start.offset..before.offset]),
+                    };
+                    return Ok(self.token(value, TokenType::String, start));
+                }
+                Some('\\') => {
+                    let decoded = decoded.get_or_insert_with(|| self.src.source[content_start.offset..before.offset].to_string());
+                    match self.escape(start) {
+                        Ok(c) => decoded.push(c),
+                        Err(err) => {
+                            // Skip the rest of the string so it is not lexed as code
+                            self.src.eat_while(|c| Some(c) != quote);
+                            self.src.eat();
+                            return Err(err);
+                        }
+                    }
+                }
+                Some(c) => {
+                    if let Some(decoded) = decoded.as_mut() {
+                        decoded.push(c);
+                    }
+                }
+            }
         }
-        else if src[0] == '{' {
-            tokens.push(token(&src.remove(0).to_string(), TokenType::OpenBrace));
-        } else if src[0] == '}' {
-            tokens.push(token(&src.remove(0).to_string(), TokenType::CloseBrace));
+    }
+
+    /// Eats a run of digits in `radix` with `_` separators between them.
+    /// Returns false if there were no digits.
+    fn digits(&mut self, radix: u32, start: Position) -> Result<bool, LexError> {
+        let mut previous = None;
+        while let Some(c) = self.src.peek() {
+            if c == '_' {
+                if !previous.is_some_and(|p: char| p.is_digit(radix)) {
+                    self.src.eat();
+                    return Err(self.error(start, "Digit separators '_' must be placed between digits."));
+                }
+            } else if !c.is_digit(radix) {
+                break;
+            }
+            previous = Some(c);
+            self.src.eat();
         }
-        else if src[0] == '[' {
-            tokens.push(token(&src.remove(0).to_string(), TokenType::OpenBracket));
-        } else if src[0] == ']' {
-            tokens.push(token(&src.remove(0).to_string(), TokenType::CloseBracket));
+        if previous == Some('_') {
+            return Err(self.error(start, "Digit separators '_' must be placed between digits."));
         }
-        else if src[0] == ':' {
-            tokens.push(token(&src.remove(0).to_string(), TokenType::Colon));
+        Ok(previous.is_some())
+    }
+
+    /// Decimal (`1_000`, `1.5`, `2e-9`), hex (`0xFF`), binary (`0b1010`)
+    /// or octal (`0o17`) literal. The token keeps the literal's source text.
+    fn number(&mut self, start: Position) -> Result<Token<'a>, LexError> {
+        let radix = match (self.src.peek(), self.src.peek_next()) {
+            (Some('0'), Some('x' | 'X')) => Some((16, "hexadecimal")),
+            (Some('0'), Some('b' | 'B')) => Some((2, "binary")),
+            (Some('0'), Some('o' | 'O')) => Some((8, "octal")),
+            _ => None,
+        };
+
+        if let Some((radix, name)) = radix {
+            self.src.eat();
+            self.src.eat();
+            if !self.digits(radix, start)? {
+                return Err(self.error(start, format!("Expected {} digits after {}.", name, self.src.slice_from(start))));
+            }
+        } else {
+            self.digits(10, start)?;
+            // `1.foo` is member access on 1, so the dot must be followed by a digit
+            if self.src.peek() == Some('.') && self.src.peek_next().is_some_and(|c| c.is_ascii_digit()) {
+                self.src.eat();
+                self.digits(10, start)?;
+            }
+            if let Some('e' | 'E') = self.src.peek() {
+                self.src.eat();
+                if let Some('+' | '-') = self.src.peek() {
+                    self.src.eat();
+                }
+                if !self.digits(10, start)? {
+                    return Err(self.error(start, format!("Expected digits in the exponent of {}.", self.src.slice_from(start))));
+                }
+            }
         }
-        else if src[0] == ',' {
-            tokens.push(token(&src.remove(0).to_string(), TokenType::Comma));
+
+        match (self.src.peek(), self.src.peek_next()) {
+            (Some('.'), Some(next)) if next.is_ascii_digit() => {
+                self.src.eat();
+                self.src.eat_while(|c| c.is_ascii_digit() || c == '.');
+                return Err(self.error(start, format!("Invalid numeric literal {}: more than one decimal point.", self.src.slice_from(start))));
+            }
+            (Some(c), _) if is_ident_continue(c) => {
+                self.src.eat();
+                return Err(self.error(start, format!("Unexpected character '{}' in numeric literal.", c)));
+            }
+            _ => {}
         }
-        else if src[0] == '+' || src[0] == '-' || src[0] == '*' || src[0] == '/' || src[0] == '%' {
-            tokens.push(token(&src.remove(0).to_string(), TokenType::BinaryOperator));
+
+        Ok(self.token(self.src.slice_from(start), TokenType::Number, start))
+    }
+
+    /// Skips a `/* */` comment, which may contain nested block comments.
+    fn skip_block_comment(&mut self, start: Position) -> Result<(), LexError> {
+        let mut depth = 0;
+        loop {
+            match (self.src.peek(), self.src.peek_next()) {
+                (None, _) => return Err(self.error(start, "Unterminated block comment.")),
+                (Some('/'), Some('*')) => {
+                    self.src.eat();
+                    self.src.eat();
+                    depth += 1;
+                }
+                (Some('*'), Some('/')) => {
+                    self.src.eat();
+                    self.src.eat();
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                _ => {
+                    self.src.eat();
+                }
+            }
         }
-        else if src[0] == '=' {
-            tokens.push(token(&src.remove(0).to_string(), TokenType::Equals));
-        } else if src[0] == ';'{
-            tokens.push(token(&src.remove(0).to_string(), TokenType::Semicolon));
-        } else {
-            if isnumber(&src[0].to_string()) {
-                let mut num = String::new();
-                while !src.is_empty() && (isnumber(&src[0].to_string()) || src[0] == '.') {
-                    num.push(src.remove(0));
-                }
-                tokens.push(token(&num, TokenType::Number));
-            } else if isalpha(&src[0].to_string()) {
-                let mut ident = String::new();
-                while !src.is_empty() && isalpha(&src[0].to_string()) {
-                    ident.push(src.remove(0));
-                }
-                let reseverd = keyword(ident.clone());
-                if reseverd != TokenType::Nil {
-                    tokens.push(token(&ident, reseverd));
-                } else {
-                    tokens.push(token(&ident, TokenType::Identifier));
-                }
-            } else if isskipabble(&src[0].to_string()) {
-                src.remove(0);
+    }
+
+    fn next_token(&mut self) -> Result<Token<'a>, LexError> {
+        loop {
+            let start = self.src.pos;
+            let c = match self.src.peek() {
+                Some(c) => c,
+                None => return Ok(self.token("EOF", TokenType::EOF, start)),
+            };
+
+            match (c, self.src.peek_next()) {
+                ('/', Some('/')) => {
+                    self.src.eat_while(|c| c != '\n');
+                    continue;
+                }
+                ('/', Some('*')) => {
+                    self.skip_block_comment(start)?;
+                    continue;
+                }
+                ('.', Some(next)) if next.is_ascii_digit() => {
+                    self.src.eat();
+                    self.src.eat_while(is_ident_continue);
+                    return Err(self.error(start, "Numeric literals cannot start with '.'; add a leading zero, e.g. 0.5."));
+                }
+                ('=', Some('=')) | ('!', Some('=')) |
+                ('<', Some('=')) | ('>', Some('=')) |
+                ('&', Some('&')) | ('|', Some('|')) => {
+                    self.src.eat();
+                    self.src.eat();
+                    return Ok(self.token(self.src.slice_from(start), TokenType::BinaryOperator, start));
+                }
+                _ => {}
+            }
+
+            let single = match c {
+                '(' => Some(TokenType::OpenParen),
+                ')' => Some(TokenType::CloseParen),
+                '{' => Some(TokenType::OpenBrace),
+                '}' => Some(TokenType::CloseBrace),
+                '[' => Some(TokenType::OpenBracket),
+                ']' => Some(TokenType::CloseBracket),
+                ':' => Some(TokenType::Colon),
+                ',' => Some(TokenType::Comma),
+                '.' => Some(TokenType::Dot),
+                '+' | '-' | '*' | '/' | '%' | '<' | '>' => Some(TokenType::BinaryOperator),
+                '=' => Some(TokenType::Equals),
+                '!' => Some(TokenType::Bang),
+                ';' => Some(TokenType::Semicolon),
+                _ => None
+            };
+
+            if let Some(type_) = single {
+                self.src.eat();
+                return Ok(self.token(self.src.slice_from(start), type_, start));
+            } else if c == '"' || c == '\'' {
+                return self.string(start);
+            } else if c.is_ascii_digit() {
+                // On error, skip the rest of the malformed literal
+                return self.number(start).inspect_err(|_| self.src.eat_while(is_ident_continue));
+            } else if is_ident_start(c) {
+                self.src.eat();
+                self.src.eat_while(is_ident_continue);
+                let ident = self.src.slice_from(start);
+                return Ok(self.token(ident, keyword(ident), start));
+            } else if isskipabble(c) {
+                self.src.eat();
             } else {
-                src.remove(0);
-                println!("Unreconized character found in code: {}", &src[0].to_string());
+                self.src.eat();
+                return Err(self.error(start, format!("Unreconized character found in code: {}", c)));
             }
         }
     }
-    tokens.push(Token::new("EOF".to_string(), TokenType::EOF));
-    tokens
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Token<'a>, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let token = self.next_token();
+        self.finished = matches!(&token, Ok(token) if token.type_ == TokenType::EOF);
+        Some(token)
+    }
+}
+
+/// Value of a `Number` token's text.
+pub fn number_value(literal: &str) -> Option<f64> {
+    let literal = literal.replace('_', "");
+    let radix = match literal.get(..2) {
+        Some("0x" | "0X") => 16,
+        Some("0b" | "0B") => 2,
+        Some("0o" | "0O") => 8,
+        _ => return literal.parse().ok(),
+    };
+    let mut value = 0.0;
+    for c in literal[2..].chars() {
+        value = value * radix as f64 + c.to_digit(radix)? as f64;
+    }
+    Some(value)
+}
+
+pub fn tokenize<'a>(source_code: &'a str, file: &str) -> Result<Vec<Token<'a>>, SunError> {
+    Ok(Lexer::new(source_code, file).collect::<Result<_, _>>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str) -> Vec<TokenType> {
+        tokenize(source, "<test>").unwrap().into_iter().map(|token| token.type_).collect()
+    }
+
+    /// Message of the first error lexing `source`.
+    fn error(source: &str) -> String {
+        Lexer::new(source, "<test>").find_map(Result::err).expect("expected a lex error").message
+    }
+
+    #[test]
+    fn token_kinds() {
+        use TokenType::*;
+        assert_eq!(kinds("let x = 1;"), [Let, Identifier, Equals, Number, Semicolon, EOF]);
+        assert_eq!(kinds("const f = fn(a, b) { return a >= b && !nil; };"), [
+            Const, Identifier, Equals, Fn, OpenParen, Identifier, Comma, Identifier, CloseParen,
+            OpenBrace, Return, Identifier, BinaryOperator, Identifier, BinaryOperator, Bang, Identifier,
+            Semicolon, CloseBrace, Semicolon, EOF,
+        ]);
+        assert_eq!(kinds("if (typeof o.k == 'a') {} else [1]"), [
+            If, OpenParen, Typeof, Identifier, Dot, Identifier, BinaryOperator, String, CloseParen,
+            OpenBrace, CloseBrace, Else, OpenBracket, Number, CloseBracket, EOF,
+        ]);
+        assert_eq!(kinds("while { x: _y$ }"), [While, OpenBrace, Identifier, Colon, Identifier, CloseBrace, EOF]);
+    }
+
+    #[test]
+    fn identifiers() {
+        let tokens = tokenize("_a $b c1 café 名前 x\u{301}", "<test>").unwrap();
+        let values: Vec<_> = tokens.iter().map(|token| token.value.as_ref()).collect();
+        assert_eq!(values, ["_a", "$b", "c1", "café", "名前", "x\u{301}", "EOF"]);
+        assert!(tokens[..6].iter().all(|token| token.type_ == TokenType::Identifier));
+        assert_eq!(error("1 · 2"), "Unreconized character found in code: ·");
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        use TokenType::*;
+        assert_eq!(kinds("a // b\n/* c /* nested */ d */ e"), [Identifier, Identifier, EOF]);
+    }
+
+    #[test]
+    fn spans() {
+        let tokens = tokenize("let föo =\n  \"hi\";", "<test>").unwrap();
+        let spans: Vec<_> = tokens.iter().map(|token| (token.span.start, token.span.end, token.span.line, token.span.column)).collect();
+        assert_eq!(spans, [(0, 3, 1, 1), (4, 8, 1, 5), (9, 10, 1, 9), (13, 17, 2, 3), (17, 18, 2, 7), (18, 18, 2, 8)]);
+        assert_eq!(&*tokens[0].span.file, "<test>");
+    }
+
+    #[test]
+    fn token_values() {
+        let tokens = tokenize(r#"0x1F 1_000 'plain' "e\tsc\u{41}pe \"q\" \\""#, "<test>").unwrap();
+        let values: Vec<_> = tokens.iter().map(|token| token.value.as_ref()).collect();
+        assert_eq!(values, ["0x1F", "1_000", "plain", "e\tscApe \"q\" \\", "EOF"]);
+        assert!(matches!(tokens[2].value, Cow::Borrowed(_)));
+        assert!(matches!(tokens[3].value, Cow::Owned(_)));
+    }
+
+    #[test]
+    fn number_values() {
+        assert_eq!(number_value("42"), Some(42.0));
+        assert_eq!(number_value("1_000_000"), Some(1_000_000.0));
+        assert_eq!(number_value("3.25"), Some(3.25));
+        assert_eq!(number_value("2e3"), Some(2000.0));
+        assert_eq!(number_value("5E-1"), Some(0.5));
+        assert_eq!(number_value("0xff"), Some(255.0));
+        assert_eq!(number_value("0XFF_FF"), Some(65535.0));
+        assert_eq!(number_value("0b1010"), Some(10.0));
+        assert_eq!(number_value("0o17"), Some(15.0));
+        assert_eq!(number_value("0b12"), None);
+        assert_eq!(number_value("abc"), None);
+    }
+
+    #[test]
+    fn error_messages() {
+        assert_eq!(error("\"abc"), "Unterminated string literal.");
+        assert_eq!(error("\"abc\\"), "Unterminated string literal.");
+        assert_eq!(error("\"\\u41\""), "Expected '{' following \\u in unicode escape.");
+        assert_eq!(error("\"\\u{41\""), "Unicode escape missing closing '}'.");
+        assert_eq!(error("\"\\u{D800}\""), "Invalid unicode escape: \\u{D800}");
+        assert_eq!(error("\"\\q\""), "Unknown escape sequence: \\q");
+        assert_eq!(error("1__0"), "Digit separators '_' must be placed between digits.");
+        assert_eq!(error("10_"), "Digit separators '_' must be placed between digits.");
+        assert_eq!(error("0x"), "Expected hexadecimal digits after 0x.");
+        assert_eq!(error("0b"), "Expected binary digits after 0b.");
+        assert_eq!(error("0o"), "Expected octal digits after 0o.");
+        assert_eq!(error("1e"), "Expected digits in the exponent of 1e.");
+        assert_eq!(error("1.2.3"), "Invalid numeric literal 1.2.3: more than one decimal point.");
+        assert_eq!(error("1a"), "Unexpected character 'a' in numeric literal.");
+        assert_eq!(error("/* /* */"), "Unterminated block comment.");
+        assert_eq!(error(".5"), "Numeric literals cannot start with '.'; add a leading zero, e.g. 0.5.");
+        assert_eq!(error("@"), "Unreconized character found in code: @");
+    }
+
+    #[test]
+    fn error_spans() {
+        let err = Lexer::new("a\n  \"\\q\"", "<test>").find_map(Result::err).unwrap();
+        assert_eq!((err.span.start, err.span.end, err.span.line, err.span.column), (4, 7, 2, 3));
+        // Reported where the hex digits stop, not at the end of the input
+        let err = Lexer::new("x = \"\\u{41\";", "<test>").find_map(Result::err).unwrap();
+        assert_eq!((err.span.start, err.span.column), (10, 11));
+        let err = Lexer::new("a\n  1.2.3", "<test>").find_map(Result::err).unwrap();
+        assert_eq!((err.span.start, err.span.end, err.span.line, err.span.column), (4, 9, 2, 3));
+    }
+
+    #[test]
+    fn continues_after_errors() {
+        let results: Vec<_> = Lexer::new("a @ b", "<test>").collect();
+        assert_eq!(results.len(), 4);
+        assert!(results[1].is_err());
+        assert_eq!(results[2].as_ref().unwrap().value, "b");
+        assert_eq!(results[3].as_ref().unwrap().type_, TokenType::EOF);
+        assert!(tokenize("a @ b", "<test>").is_err());
+    }
+
+    #[test]
+    fn stops_after_eof() {
+        let mut lexer = Lexer::new("a", "<test>");
+        assert_eq!(lexer.next().unwrap().unwrap().value, "a");
+        assert_eq!(lexer.next().unwrap().unwrap().type_, TokenType::EOF);
+        assert!(lexer.next().is_none());
+    }
 }
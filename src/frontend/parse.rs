@@ -1,200 +1,673 @@
-#![feature(arbitrary_self_types)]
+use std::collections::VecDeque;
+use std::rc::Rc;
 
-use std::collections::HashMap;
+use crate::ast::{ArrayLiteral, AssignmentExpr, BinaryExpr, BlockStmt, CallExpr, ErrorExpr, ErrorStmt, Expr, Function, Identifier, IfStmt, MemberExpr, NumericLiteral, ObjectLiteral, Program, Property, ReturnStmt, Stmt, StringLiteral, UnaryExpr, VarDeclaration, WhileStmt};
+use crate::error::SunError;
+use crate::lexer::{number_value, Lexer, Span, Token, TokenType};
 
-use crate::ast::{AssignmentExpr, BinaryExpr, Expr, Identifier, NodeType, NumericLiteral, ObjectLiteral, Program, Property, Stmt, VarDeclaration};
-use crate::lexer::{tokenize, Token, TokenType};
+/// Deepest nesting of statements and expressions a program may have.
+/// Parsing, running and freeing a syntax tree all recurse over it, so
+/// deeper input would overflow the native stack.
+pub const MAX_NESTING_DEPTH: usize = 256;
 
-pub struct Parser {
-    tokens: Vec<Token>,
+/// Native stack the parser keeps free. Input is rejected as nested too
+/// deeply once less is left, for threads too small for the full
+/// `MAX_NESTING_DEPTH` (debug builds need tens of KiB per level).
+const STACK_RED_ZONE: usize = 128 * 1024;
+
+pub struct Parser<'a> {
+    file: String,
+    lexer: Option<Lexer<'a>>,
+    // Tokens pulled from the lexer but not consumed yet; the front is the current token
+    lookahead: VecDeque<Token<'a>>,
+    diagnostics: Vec<SunError>,
+    function_depth: usize,
+    nesting: usize,
+    // Brackets eaten and not closed yet
+    open_groups: usize,
+    too_deep: bool,
 }
 
-impl Parser {
+impl Default for Parser<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Parser<'a> {
     pub fn new() -> Self {
-        Parser { tokens: Vec::new() }
+        Self::with_file("<input>")
+    }
+
+    /// Parser whose tokens and nodes report positions inside `file`.
+    pub fn with_file(file: &str) -> Self {
+        Parser { file: file.to_string(), lexer: None, lookahead: VecDeque::new(), diagnostics: Vec::new(), function_depth: 0, nesting: 0, open_groups: 0, too_deep: false }
+    }
+
+    /// Pulls tokens from the lexer until the current and next token are buffered.
+    fn fill(&mut self) {
+        while self.lookahead.len() < 2 {
+            match self.lexer.as_mut().and_then(Iterator::next) {
+                Some(Ok(token)) => self.lookahead.push_back(token),
+                Some(Err(err)) => {
+                    // Stand in with an error token so the parser can carry on past it
+                    self.lookahead.push_back(Token::new("", TokenType::Error, err.span.clone()));
+                    self.diagnostics.push(err.into());
+                }
+                None => break,
+            }
+        }
     }
 
     fn not_eof(&self) -> bool {
-        !self.tokens.is_empty() && self.tokens[0].type_ != TokenType::EOF
+        self.at().type_ != TokenType::EOF
+    }
+
+    fn at(&self) -> &Token<'a> {
+        &self.lookahead[0]
+    }
+
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.lookahead.get(1)
+    }
+
+    fn eat(&mut self) -> Token<'a> {
+        // EOF is never consumed, so there is always a current token
+        if !self.not_eof() {
+            return self.at().clone();
+        }
+        let token = self.lookahead.pop_front().unwrap();
+        match token.type_ {
+            TokenType::OpenParen | TokenType::OpenBrace | TokenType::OpenBracket => self.open_groups += 1,
+            TokenType::CloseParen | TokenType::CloseBrace | TokenType::CloseBracket => self.open_groups = self.open_groups.saturating_sub(1),
+            _ => {}
+        }
+        self.fill();
+        token
+    }
+
+    /// Eats the current token if it is a `type_`. A mismatched token is
+    /// left in place so error recovery can resynchronize on it.
+    fn expect(&mut self, type_: TokenType, err: &str) -> Result<Token<'a>, SunError> {
+        let current = self.at();
+        if current.type_ != type_ {
+            return Err(SunError::parse(&current.span, format!("{} {:?} - Expecting: {:?}", err, current.value, type_)));
+        }
+
+        Ok(self.eat())
     }
 
-    fn at(&self) -> &Token {
-        &self.tokens[0]
+    /// Records `err` unless an error was already reported at the same spot,
+    /// which happens when the parser trips over a token the lexer rejected.
+    /// Goes one level deeper into the tree. Callers undo it once the
+    /// nested node is parsed; `parse_stmt_recovering` undoes it on errors.
+    fn nest(&mut self) -> Result<(), SunError> {
+        let stack_low = stacker::remaining_stack().is_some_and(|remaining| remaining < STACK_RED_ZONE);
+        if self.nesting >= MAX_NESTING_DEPTH || stack_low {
+            self.too_deep = true;
+            return Err(SunError::parse(&self.at().span, "Expression nested too deeply"));
+        }
+        self.nesting += 1;
+        Ok(())
     }
 
-    fn eat(&mut self) -> Token {
-        self.tokens.remove(0)
+    fn report(&mut self, err: SunError) {
+        if !self.diagnostics.iter().any(|reported| reported.span().start == err.span().start) {
+            self.diagnostics.push(err);
+        }
     }
 
-    fn expect(&mut self, type_: TokenType, err: &str) -> Token {
-        let prev = self.eat();
-        if prev.type_ != type_ {
-            eprintln!("Parser Error:\n{} {:?} - Expecting: {:?}", err, prev, type_);
-            std::process::exit(1);
+    /// Skips ahead to where the next statement is likely to start: just
+    /// past a `;`, or at a brace or a statement keyword.
+    fn synchronize(&mut self) {
+        while self.not_eof() {
+            match self.at().type_ {
+                TokenType::Semicolon => {
+                    self.eat();
+                    return;
+                }
+                TokenType::OpenBrace | TokenType::CloseBrace | TokenType::Let | TokenType::Const | TokenType::If |
+                TokenType::While | TokenType::Fn | TokenType::Return => return,
+                _ => {
+                    self.eat();
+                }
+            }
         }
+    }
 
-        prev
+    /// Parses a statement, turning a syntax error into a `Stmt::Error`
+    /// node and resuming at the next statement boundary.
+    fn parse_stmt_recovering(&mut self) -> Stmt {
+        let start = self.at().span.clone();
+        let nesting = self.nesting;
+        let open_groups = self.open_groups;
+        match self.parse_stmt() {
+            Ok(stmt) => stmt,
+            Err(err) => {
+                self.nesting = nesting;
+                let span = start.to(err.span());
+                // Always make progress, even if the error was on the first token
+                if self.at().span.start == start.start {
+                    self.eat();
+                }
+                // Too deep to parse, so skip to where the statement's brackets close
+                if std::mem::take(&mut self.too_deep) {
+                    while self.not_eof() && self.open_groups > open_groups {
+                        self.eat();
+                    }
+                }
+                self.report(err);
+                self.synchronize();
+                Stmt::Error(ErrorStmt { span })
+            }
+        }
     }
 
-    pub fn produce_ast(&mut self, source_code: &str) -> Program {
-        self.tokens = tokenize(source_code);
-        let mut program = Program { kind: NodeType::Program, body: Vec::new() };
+    /// Parses the whole input without stopping at the first error. Returns
+    /// every lexer and parser error, in source order, with a best-effort
+    /// tree where statements that failed to parse are `Stmt::Error` nodes.
+    pub fn produce_ast_recovering(&mut self, source_code: &'a str) -> (Program, Vec<SunError>) {
+        self.lexer = Some(Lexer::new(source_code, &self.file));
+        self.lookahead.clear();
+        self.diagnostics.clear();
+        self.function_depth = 0;
+        self.nesting = 0;
+        self.open_groups = 0;
+        self.too_deep = false;
+        self.fill();
+        let span = Span { file: self.file.as_str().into(), start: 0, end: source_code.len(), line: 1, column: 1 };
+        let mut program = Program { span, body: Vec::new() };
 
         while self.not_eof() {
-            program.body.push(self.parse_stmt());
+            program.body.push(self.parse_stmt_recovering());
         }
-        
-        program
+
+        let mut diagnostics = std::mem::take(&mut self.diagnostics);
+        diagnostics.sort_by_key(|err| err.span().start);
+        (program, diagnostics)
     }
 
-    fn parse_stmt(&mut self) -> Box<dyn Stmt> {
-        match self.at().type_ {
+    /// Parses the whole input, failing with the first error in it.
+    pub fn produce_ast(&mut self, source_code: &'a str) -> Result<Program, SunError> {
+        let (program, diagnostics) = self.produce_ast_recovering(source_code);
+        match diagnostics.into_iter().next() {
+            Some(err) => Err(err),
+            None => Ok(program),
+        }
+    }
+
+    fn parse_stmt(&mut self) -> Result<Stmt, SunError> {
+        self.nest()?;
+        let stmt = match self.at().type_ {
             TokenType::Const | TokenType::Let => {
                 self.parse_var_declaration()
             }
+            TokenType::If => {
+                self.parse_if_stmt()
+            }
+            TokenType::While => {
+                self.parse_while_stmt()
+            }
+            TokenType::OpenBrace => {
+                Ok(Stmt::Block(self.parse_block_stmt()?))
+            }
+            TokenType::Fn if self.peek().is_some_and(|token| token.type_ == TokenType::Identifier) => {
+                Ok(Stmt::FunctionDeclaration(self.parse_function()?))
+            }
+            TokenType::Return => {
+                self.parse_return_stmt()
+            }
             _ => {
-                self.parse_expr().into_boxed_stmt()
+                let expr = self.parse_expr()?;
+                if self.at().type_ == TokenType::Semicolon {
+                    self.eat();
+                }
+                Ok(Stmt::Expr(expr))
             }
+        }?;
+        self.nesting -= 1;
+        Ok(stmt)
+    }
+
+    fn parse_block_stmt(&mut self) -> Result<BlockStmt, SunError> {
+        let open = self.expect(TokenType::OpenBrace, "Expected opening brace to start block.")?;
+        let mut body = Vec::new();
+
+        while self.not_eof() && self.at().type_ != TokenType::CloseBrace {
+            body.push(self.parse_stmt_recovering());
+        }
+
+        let close = self.expect(TokenType::CloseBrace, "Block missing closing brace.")?;
+        Ok(BlockStmt { span: open.span.to(&close.span), body })
+    }
+
+    fn parse_function(&mut self) -> Result<Function, SunError> {
+        let keyword = self.eat();
+        let name = if self.at().type_ == TokenType::Identifier {
+            Some(self.eat().value.into_owned())
+        } else {
+            None
+        };
+
+        self.expect(TokenType::OpenParen, "Expected opening parenthesis following fn keyword.")?;
+        let mut parameters = Vec::new();
+        while self.not_eof() && self.at().type_ != TokenType::CloseParen {
+            parameters.push(self.expect(TokenType::Identifier, "Expected parameter name in function declaration.")?.value.into_owned());
+            if self.at().type_ != TokenType::CloseParen {
+                self.expect(TokenType::Comma, "Expected comma or closing parenthesis following parameter.")?;
+            }
+        }
+        self.expect(TokenType::CloseParen, "Function parameter list missing closing parenthesis.")?;
+
+        self.function_depth += 1;
+        let body = self.parse_block_stmt();
+        self.function_depth -= 1;
+        let body = body?;
+
+        Ok(Function { span: keyword.span.to(&body.span), name, parameters, body: Rc::new(body.body) })
+    }
+
+    fn parse_return_stmt(&mut self) -> Result<Stmt, SunError> {
+        let keyword = self.eat();
+        if self.function_depth == 0 {
+            return Err(SunError::parse(&keyword.span, "Return statement outside of function."));
+        }
+
+        let value = match self.at().type_ {
+            TokenType::Semicolon | TokenType::CloseBrace => None,
+            _ => Some(self.parse_expr()?),
+        };
+        let span = match &value {
+            Some(value) => keyword.span.to(value.span()),
+            None => keyword.span.clone(),
+        };
+        if self.at().type_ == TokenType::Semicolon {
+            self.eat();
         }
+
+        Ok(Stmt::Return(ReturnStmt { span, value }))
+    }
+
+    fn parse_condition(&mut self, keyword: &str) -> Result<Expr, SunError> {
+        self.expect(TokenType::OpenParen, &format!("Expected opening parenthesis following {} keyword.", keyword))?;
+        let condition = self.parse_expr()?;
+        self.expect(TokenType::CloseParen, &format!("Expected closing parenthesis after {} condition.", keyword))?;
+        Ok(condition)
+    }
+
+    fn parse_if_stmt(&mut self) -> Result<Stmt, SunError> {
+        let keyword = self.eat();
+        let condition = self.parse_condition("if")?;
+        let then_branch = Box::new(self.parse_stmt()?);
+
+        let else_branch = if self.at().type_ == TokenType::Else {
+            self.eat();
+            Some(Box::new(self.parse_stmt()?))
+        } else {
+            None
+        };
+
+        let end = else_branch.as_ref().unwrap_or(&then_branch).span();
+        Ok(Stmt::If(IfStmt { span: keyword.span.to(end), condition, then_branch, else_branch }))
+    }
+
+    fn parse_while_stmt(&mut self) -> Result<Stmt, SunError> {
+        let keyword = self.eat();
+        let condition = self.parse_condition("while")?;
+        let body = Box::new(self.parse_stmt()?);
+
+        Ok(Stmt::While(WhileStmt { span: keyword.span.to(body.span()), condition, body }))
     }
     
-    fn parse_var_declaration(&mut self) -> Box<dyn Stmt> {
-        let is_constant = self.eat().type_ == TokenType::Const;
+    fn parse_var_declaration(&mut self) -> Result<Stmt, SunError> {
+        let keyword = self.eat();
+        let is_constant = keyword.type_ == TokenType::Const;
         let identifier = self.expect(
             TokenType::Identifier,
             "Expected identifier name following let | const keywords."
-        ).value.clone();
+        )?.value.into_owned();
 
         if self.at().type_ == TokenType::Semicolon {
-            self.eat(); // Consome o ponto e vírgula
+            let semicolon = self.eat(); // Consome o ponto e vírgula
             if is_constant {
-                panic!("Must assign value to constant expression. No value provided.");
+                return Err(SunError::parse(&keyword.span, "Must assign value to constant expression. No value provided."));
             }
 
-            return Box::new(VarDeclaration {
-                kind: NodeType::VarDeclarationStmt,
+            return Ok(Stmt::VarDeclaration(VarDeclaration {
+                span: keyword.span.to(&semicolon.span),
                 identifier,
                 value: None,
                 constant: false,
-            });
+            }));
         }
 
         self.expect(
             TokenType::Equals,
             "Expected equals token following identifier in var declaration."
-        );
-
-        let declaration = VarDeclaration {
-            kind: NodeType::VarDeclarationStmt,
-            identifier,
-            value: Some(self.parse_expr()),
-            constant: is_constant,
-        };
+        )?;
 
-        self.expect(
+        let value = self.parse_expr()?;
+        let semicolon = self.expect(
             TokenType::Semicolon,
             "Variable declaration statement must end with semicolon."
-        );
-        
-        Box::new(declaration)
+        )?;
+
+        Ok(Stmt::VarDeclaration(VarDeclaration {
+            span: keyword.span.to(&semicolon.span),
+            identifier,
+            value: Some(value),
+            constant: is_constant,
+        }))
     }
     
-    fn parse_assignment_expr(&mut self) -> Box<dyn Expr> {
-        let left = self.parse_object_literal() as Box<dyn Expr>;
+    fn parse_assignment_expr(&mut self) -> Result<Expr, SunError> {
+        self.nest()?;
+        let left = self.parse_logical_or_expr()?;
         
-        if self.at().type_ == TokenType::Equals {
+        let expr = if self.at().type_ == TokenType::Equals {
             self.eat();
-            let value = self.parse_assignment_expr();
-            return Box::new(AssignmentExpr {kind: NodeType::AssignmentExpr, assgine: left, value: value})
-        }
+            let value = self.parse_assignment_expr()?;
+            let span = left.span().to(value.span());
+            Expr::Assignment(AssignmentExpr { span, assgine: Box::new(left), value: Box::new(value) })
+        } else {
+            left
+        };
         
-        left
+        self.nesting -= 1;
+        Ok(expr)
     }
     
-    fn parse_object_literal(&mut self) -> Box<dyn Expr> {
-        if self.at().type_ != TokenType::OpenBrace {
-            return self.parse_additive_expr();
-        }
-        
-        self.eat(); // Avançar após a chave aberta
-        let mut properties: HashMap<String, Property> = HashMap::new();
+    fn parse_object_literal(&mut self) -> Result<Expr, SunError> {
+        let open = self.eat(); // Avançar após a chave aberta
+        let mut properties: Vec<Property> = Vec::new();
         
         while self.not_eof() && self.at().type_ != TokenType::CloseBrace {
-            let key = self.expect(TokenType::Identifier, "Object literal key expected").value;
+            let key_token = self.expect(TokenType::Identifier, "Object literal key expected")?;
+            let key = key_token.value.into_owned();
             
-            if self.at().type_ == TokenType::Comma {
-                self.eat(); // Avançar após a vírgula
-                properties.insert(key.clone(), Property { kind: NodeType::Property, key, value: None });
-                return Box::new(ObjectLiteral { kind: NodeType::ObjectLiteral, value: properties });
-            } else if self.at().type_ == TokenType::CloseBrace {
-                properties.insert(key.clone(), Property { kind: NodeType::Property, key, value: None });
-                return Box::new(ObjectLiteral { kind: NodeType::ObjectLiteral, value: properties });
+            // Shorthand `{ key }` takes its value from the variable with the same name
+            if self.at().type_ == TokenType::Comma || self.at().type_ == TokenType::CloseBrace {
+                properties.push(Property { span: key_token.span, key, value: None });
+            } else {
+                self.expect(TokenType::Colon, "Missing colon following identifier in ObjectExpr")?;
+                let value = self.parse_expr()?;
+                let span = key_token.span.to(value.span());
+                properties.push(Property { span, key, value: Some(value) });
             }
             
-            self.expect(TokenType::Colon, "Missing colon following identifier in ObjectExpr");
-            let value = self.parse_expr();
-            
-            properties.insert(key.clone(), Property { kind: NodeType::Property, key, value: Some(value) });
-            
             if self.at().type_ != TokenType::CloseBrace {
-                self.expect(TokenType::Comma, "Expected comma or closing bracket following property");
+                self.expect(TokenType::Comma, "Expected comma or closing bracket following property")?;
             }
         }
         
-        self.expect(TokenType::CloseBrace, "Object literal missing closing brace.");
-        Box::new(ObjectLiteral { kind: NodeType::ObjectLiteral, value: properties })
+        let close = self.expect(TokenType::CloseBrace, "Object literal missing closing brace.")?;
+        Ok(Expr::ObjectLiteral(ObjectLiteral { span: open.span.to(&close.span), properties }))
     }
     
-    
-    fn parse_expr(&mut self) -> Box<dyn Expr> {
+    fn parse_expr(&mut self) -> Result<Expr, SunError> {
         self.parse_assignment_expr()
     }
     
-    fn parse_additive_expr(&mut self) -> Box<dyn Expr> {
-        let mut left = self.parse_multiplicative_expr();
-    
-        while self.at().value == "+" || self.at().value == "-" {
-            let operator = self.eat().value.clone();
-            let right = self.parse_multiplicative_expr();
-            left = Box::new(BinaryExpr { kind: NodeType::BinaryExpr, left, right, operator });
+    fn parse_binary_level(&mut self, operators: &[&str], next: fn(&mut Self) -> Result<Expr, SunError>) -> Result<Expr, SunError> {
+        let mut left = next(self)?;
+        // `a + b + c` nests to the left, one level per operator
+        let nesting = self.nesting;
+
+        while self.at().type_ == TokenType::BinaryOperator && operators.contains(&self.at().value.as_ref()) {
+            self.nest()?;
+            let operator = self.eat().value.into_owned();
+            let right = next(self)?;
+            let span = left.span().to(right.span());
+            left = Expr::Binary(BinaryExpr { span, left: Box::new(left), right: Box::new(right), operator });
         }
-    
-        left
+
+        self.nesting = nesting;
+        Ok(left)
+    }
+
+    fn parse_logical_or_expr(&mut self) -> Result<Expr, SunError> {
+        self.parse_binary_level(&["||"], Self::parse_logical_and_expr)
+    }
+
+    fn parse_logical_and_expr(&mut self) -> Result<Expr, SunError> {
+        self.parse_binary_level(&["&&"], Self::parse_equality_expr)
+    }
+
+    fn parse_equality_expr(&mut self) -> Result<Expr, SunError> {
+        self.parse_binary_level(&["==", "!="], Self::parse_comparison_expr)
+    }
+
+    fn parse_comparison_expr(&mut self) -> Result<Expr, SunError> {
+        self.parse_binary_level(&["<", "<=", ">", ">="], Self::parse_additive_expr)
+    }
+
+    fn parse_additive_expr(&mut self) -> Result<Expr, SunError> {
+        self.parse_binary_level(&["+", "-"], Self::parse_multiplicative_expr)
+    }
+
+    fn parse_multiplicative_expr(&mut self) -> Result<Expr, SunError> {
+        self.parse_binary_level(&["/", "*", "%"], Self::parse_unary_expr)
+    }
+
+    fn is_unary_operator(&self) -> bool {
+        match self.at().type_ {
+            TokenType::Bang | TokenType::Typeof => true,
+            TokenType::BinaryOperator => self.at().value == "-" || self.at().value == "+",
+            _ => false,
+        }
+    }
+
+    fn parse_unary_expr(&mut self) -> Result<Expr, SunError> {
+        if self.is_unary_operator() {
+            self.nest()?;
+            let operator = self.eat();
+            let argument = self.parse_unary_expr()?;
+            self.nesting -= 1;
+            let span = operator.span.to(argument.span());
+            return Ok(Expr::Unary(UnaryExpr { span, operator: operator.value.into_owned(), argument: Box::new(argument) }));
+        }
+
+        self.parse_call_member_expr()
     }
-    
 
-    fn parse_multiplicative_expr(&mut self) -> Box<dyn Expr> {
-        let mut left = self.parse_primary_expr();
+    fn parse_call_member_expr(&mut self) -> Result<Expr, SunError> {
+        let mut callee = self.parse_primary_expr()?;
+        // `a.b(c)[d]` nests to the left, one level per call or member access
+        let nesting = self.nesting;
 
-        while self.at().value == "/" || self.at().value == "*" || self.at().value == "%" {
-            let operator = self.eat().value.clone();
-            let right = self.parse_primary_expr();
-            left = Box::new(BinaryExpr { kind: NodeType::BinaryExpr, left, right, operator });
+        loop {
+            if matches!(self.at().type_, TokenType::OpenParen | TokenType::Dot | TokenType::OpenBracket) {
+                self.nest()?;
+            }
+            match self.at().type_ {
+                TokenType::OpenParen => callee = self.parse_call_expr(callee)?,
+                TokenType::Dot => {
+                    self.eat();
+                    let token = self.expect(TokenType::Identifier, "Expected property name following dot.")?;
+                    let span = callee.span().to(&token.span);
+                    let property = Expr::Identifier(Identifier { span: token.span, symbol: token.value.into_owned() });
+                    callee = Expr::Member(MemberExpr { span, object: Box::new(callee), property: Box::new(property), computed: false });
+                }
+                TokenType::OpenBracket => {
+                    self.eat();
+                    let property = self.parse_expr()?;
+                    let close = self.expect(TokenType::CloseBracket, "Computed property missing closing bracket.")?;
+                    let span = callee.span().to(&close.span);
+                    callee = Expr::Member(MemberExpr { span, object: Box::new(callee), property: Box::new(property), computed: true });
+                }
+                _ => {
+                    self.nesting = nesting;
+                    return Ok(callee);
+                }
+            }
         }
+    }
 
-        left
+    fn parse_call_expr(&mut self, callee: Expr) -> Result<Expr, SunError> {
+        self.eat();
+        let mut arguments = Vec::new();
+        while self.not_eof() && self.at().type_ != TokenType::CloseParen {
+            arguments.push(self.parse_expr()?);
+            if self.at().type_ != TokenType::CloseParen {
+                self.expect(TokenType::Comma, "Expected comma or closing parenthesis following argument.")?;
+            }
+        }
+        let close = self.expect(TokenType::CloseParen, "Call expression missing closing parenthesis.")?;
+        let span = callee.span().to(&close.span);
+        Ok(Expr::Call(CallExpr { span, callee: Box::new(callee), arguments }))
     }
 
-    fn parse_primary_expr(&mut self) -> Box<dyn Expr> {
+    fn parse_array_literal(&mut self) -> Result<Expr, SunError> {
+        let open = self.eat();
+        let mut elements = Vec::new();
+
+        while self.not_eof() && self.at().type_ != TokenType::CloseBracket {
+            elements.push(self.parse_expr()?);
+            if self.at().type_ != TokenType::CloseBracket {
+                self.expect(TokenType::Comma, "Expected comma or closing bracket following array element.")?;
+            }
+        }
+
+        let close = self.expect(TokenType::CloseBracket, "Array literal missing closing bracket.")?;
+        Ok(Expr::ArrayLiteral(ArrayLiteral { span: open.span.to(&close.span), elements }))
+    }
+
+    fn parse_primary_expr(&mut self) -> Result<Expr, SunError> {
         match self.at().type_ {
             TokenType::Identifier => {
-                let symbol = self.eat().value.clone();
-                Box::new(Identifier { kind: NodeType::Identifier, symbol })
+                let token = self.eat();
+                Ok(Expr::Identifier(Identifier { span: token.span, symbol: token.value.into_owned() }))
             }
             TokenType::Number => {
-                let value = self.eat().value.parse().expect("Failed to parse number");
-                Box::new(NumericLiteral { kind: NodeType::NumericLiteral, value })
+                let token = self.eat();
+                let value = number_value(&token.value)
+                    .ok_or_else(|| SunError::parse(&token.span, format!("Failed to parse number {:?}", token.value)))?;
+                Ok(Expr::NumericLiteral(NumericLiteral { span: token.span, value }))
+            }
+            TokenType::String => {
+                let token = self.eat();
+                Ok(Expr::StringLiteral(StringLiteral { span: token.span, value: token.value.into_owned() }))
+            }
+            TokenType::Fn => {
+                Ok(Expr::Function(self.parse_function()?))
+            }
+            TokenType::OpenBracket => {
+                self.parse_array_literal()
+            }
+            TokenType::OpenBrace => {
+                self.parse_object_literal()
+            }
+            TokenType::Error => {
+                // Already reported by the lexer
+                let token = self.eat();
+                Ok(Expr::Error(ErrorExpr { span: token.span }))
             }
             TokenType::OpenParen => {
                 self.eat();
-                let value = self.parse_expr();
-                self.expect(TokenType::CloseParen, "Unexpected token found inside parenthesised expression. Expected closing parenthesis.");
-                value
+                let value = self.parse_expr()?;
+                self.expect(TokenType::CloseParen, "Unexpected token found inside parenthesised expression. Expected closing parenthesis.")?;
+                Ok(value)
             }
             _ => {
-                println!("Unexpected token found during parsing! {:?}", self.at());
-                Box::new(Identifier { kind: NodeType::Identifier, symbol: self.eat().value })
+                Err(SunError::parse(&self.at().span, format!("Unexpected token found during parsing! {:?}", self.at().value)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "let a = 1;
+let = 2;
+const b;
+let c = @;
+return 4;
+let d = a + 1;
+";
+
+    #[test]
+    fn recovers_from_several_errors() {
+        let (program, diagnostics) = Parser::with_file("<test>").produce_ast_recovering(SOURCE);
+
+        let reported: Vec<_> = diagnostics.iter().map(|err| (err.span().line, err.span().column, err.message())).collect();
+        assert_eq!(reported, [
+            (2, 5, "Expected identifier name following let | const keywords. \"=\" - Expecting: Identifier"),
+            (3, 1, "Must assign value to constant expression. No value provided."),
+            (4, 9, "Unreconized character found in code: @"),
+            (5, 1, "Return statement outside of function."),
+        ]);
+        assert!(matches!(diagnostics[0], SunError::Parse { .. }));
+        assert!(matches!(diagnostics[2], SunError::Lex { .. }));
+
+        assert_eq!(program.body.len(), 6);
+        assert!(matches!(&program.body[0], Stmt::VarDeclaration(decl) if decl.identifier == "a"));
+        for (index, line) in [(1, 2), (2, 3), (4, 5)] {
+            match &program.body[index] {
+                Stmt::Error(error) => assert_eq!(error.span.line, line),
+                other => panic!("expected an error node for line {}, got {:?}", line, other),
             }
         }
+        match &program.body[3] {
+            Stmt::VarDeclaration(decl) => assert!(matches!(decl.value, Some(Expr::Error(_)))),
+            other => panic!("expected a declaration, got {:?}", other),
+        }
+        assert!(matches!(&program.body[5], Stmt::VarDeclaration(decl) if decl.identifier == "d"));
+    }
+
+    #[test]
+    fn recovers_inside_blocks() {
+        let (program, diagnostics) = Parser::new().produce_ast_recovering("if (x { let y = ; }\nlet z = 1;");
+        // Resyncs on the `{` and still reports the error inside the block
+        let columns: Vec<_> = diagnostics.iter().map(|err| err.span().column).collect();
+        assert_eq!(columns, [7, 17]);
+        assert!(matches!(program.body.last(), Some(Stmt::VarDeclaration(decl)) if decl.identifier == "z"));
+    }
+
+    #[test]
+    fn produce_ast_fails_with_the_first_error() {
+        let err = Parser::with_file("<test>").produce_ast(SOURCE).unwrap_err();
+        assert_eq!((err.span().line, err.span().column), (2, 5));
+        assert!(Parser::new().produce_ast("let a = 1; a + 2;").is_ok());
+    }
+
+    /// Diagnostics for `source`, parsed on a thread with `stack_size`
+    /// bytes of stack.
+    fn diagnostics_with_stack(stack_size: usize, source: String) -> Vec<String> {
+        std::thread::Builder::new()
+            .stack_size(stack_size)
+            .spawn(move || {
+                let (_, diagnostics) = Parser::new().produce_ast_recovering(&source);
+                diagnostics.iter().map(|err| err.message().to_string()).collect()
+            })
+            .unwrap()
+            .join()
+            .expect("the parser overflowed its stack")
+    }
+
+    #[test]
+    fn limits_nesting_depth() {
+        let nested = |open: &str, inner: &str, close: &str, depth: usize| {
+            format!("let a = {}{}{};", open.repeat(depth), inner, close.repeat(depth))
+        };
+        let too_deep = vec!["Expression nested too deeply".to_string()];
+        // Enough for the whole depth in a debug build, and the default for spawned threads
+        const LARGE: usize = 64 * 1024 * 1024;
+        const SMALL: usize = 2 * 1024 * 1024;
+
+        assert!(diagnostics_with_stack(LARGE, nested("(", "1", ")", MAX_NESTING_DEPTH - 10)).is_empty());
+        assert!(diagnostics_with_stack(LARGE, nested("[", "1", "]", MAX_NESTING_DEPTH - 10)).is_empty());
+        assert_eq!(diagnostics_with_stack(LARGE, nested("(", "1", ")", MAX_NESTING_DEPTH + 10)), too_deep);
+        assert_eq!(diagnostics_with_stack(SMALL, nested("(", "1", ")", 3000)), too_deep);
+        assert_eq!(diagnostics_with_stack(SMALL, nested("[", "", "]", 3000)), too_deep);
+        assert_eq!(diagnostics_with_stack(SMALL, nested("{ x: ", "1", " }", 3000)), too_deep);
+        assert_eq!(diagnostics_with_stack(SMALL, nested("-", "1", "", 3000)), too_deep);
+        assert_eq!(diagnostics_with_stack(SMALL, format!("let a = 1{};", " + 1".repeat(3000))), too_deep);
+        assert_eq!(diagnostics_with_stack(SMALL, format!("{}{}", "{".repeat(3000), "}".repeat(3000))), too_deep);
+
+        // Parsing carries on after the deeply nested statement
+        let source = format!("{}\nlet b = ;", nested("(", "1", ")", 3000));
+        assert_eq!(diagnostics_with_stack(SMALL, source).len(), 2);
     }
 }
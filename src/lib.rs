@@ -0,0 +1,27 @@
+#![allow(non_snake_case, clippy::needless_return, clippy::upper_case_acronyms)]
+//! The Sun language: lexer, parser and tree-walking interpreter.
+//!
+//! ```
+//! let mut sun = sun::Interpreter::new();
+//! sun.set_global("answer", sun::Value::Number(42.0)).unwrap();
+//! let value = sun.eval_str("answer / 2").unwrap();
+//! assert_eq!(value, sun::Value::Number(21.0));
+//! ```
+
+mod error;
+mod frontend;
+mod runtime;
+
+// Lets the modules refer to each other as `crate::lexer`, `crate::value`, ...
+use frontend::*;
+use runtime::*;
+
+pub use error::SunError;
+pub use runtime::builtins::Console;
+pub use runtime::interpreter::{read_script, Interpreter};
+pub use runtime::value::{EnumVariableType as ValueType, NativeFunction, Object, SunFunction, SunVariable as Value};
+
+// Enough of the frontend for tools that print tokens or syntax trees.
+pub use frontend::lexer::{tokenize, LexError, Lexer, Span, Token, TokenType};
+pub use frontend::parse::Parser;
+pub use frontend::ast::{self, Program};
@@ -1,26 +1,156 @@
-mod frontend;
-mod runtime;
-use frontend::ast::Stmt;
-use frontend::*;
-use runtime::interpreter::evaluate;
-use runtime::value::SunVariable;
-use runtime::*;
-
-use std::fs::File;
-use std::io::Read;
+mod repl;
+
+use sun::{read_script, tokenize, Interpreter, Parser, SunError, Value};
+
+use std::io::{self, Read};
 use std::env;
-use std::io::stdin;
-
-use crate::environmment::Environmment;
-
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let mut content = String::new();
-    File::open(&args[1]).expect("").read_to_string(&mut content);
-    let tokens = frontend::parse::Parser::new().produce_ast(&content.to_string());
-    //println!("{:#?}", tokens);
-    let mut env = environmment::createGlobalEnv();
-    if let Some(programToken) = tokens.as_program() {
-        println!("{:#?}", evaluate(programToken, &mut env));
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage:
+    sun                     start the REPL
+    sun run <file>          run a script
+    sun check <file>        parse a script and report all errors
+    sun tokens <file>       print the tokens of a script
+    sun ast <file>          print the syntax tree of a script, with
+                            error nodes where parsing failed
+    sun eval -e <code>      run code given on the command line
+
+<file> may be - to read from stdin.";
+
+/// Exit code for errors reported by the lexer, parser or interpreter.
+const EXIT_SCRIPT_ERROR: u8 = 1;
+/// Exit code for invalid command-line usage.
+const EXIT_USAGE: u8 = 2;
+/// Exit code when the input could not be read.
+const EXIT_IO_ERROR: u8 = 3;
+
+enum CliError {
+    Usage(String),
+    Io(String),
+    Script(SunError),
+    Diagnostics(Vec<SunError>),
+}
+
+impl From<SunError> for CliError {
+    fn from(err: SunError) -> Self {
+        match err {
+            SunError::Io { .. } => CliError::Io(err.to_string()),
+            err => CliError::Script(err),
+        }
+    }
+}
+
+/// Reads a script, with `-` meaning stdin. Returns the name to report
+/// positions under along with the source.
+fn read_source(path: &str) -> Result<(String, String), CliError> {
+    if path == "-" {
+        let mut content = String::new();
+        io::stdin().read_to_string(&mut content)
+            .map_err(|err| CliError::Io(format!("Cannot read from stdin: {}", err)))?;
+        return Ok(("<stdin>".to_string(), content));
+    }
+
+    Ok(read_script(path)?)
+}
+
+fn file_arg(command: &str, args: &[String]) -> Result<(String, String), CliError> {
+    match args {
+        [path] => read_source(path),
+        [] => Err(CliError::Usage(format!("sun {} expects a file argument", command))),
+        _ => Err(CliError::Usage(format!("sun {} expects a single file argument", command))),
+    }
+}
+
+fn print_result(value: Value) -> Result<(), CliError> {
+    if value != Value::Nil {
+        println!("{}", value);
+    }
+    Ok(())
+}
+
+fn cli(args: &[String]) -> Result<(), CliError> {
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => {
+            repl::start();
+            return Ok(());
+        }
+    };
+
+    match command {
+        "run" => match rest {
+            [path] if path != "-" => print_result(Interpreter::new().eval_file(path)?),
+            _ => {
+                let (file, content) = file_arg(command, rest)?;
+                print_result(Interpreter::new().eval_source(&content, &file)?)
+            }
+        },
+        "check" => {
+            let (file, content) = file_arg(command, rest)?;
+            let (_, diagnostics) = Parser::with_file(&file).produce_ast_recovering(&content);
+            if diagnostics.is_empty() {
+                Ok(())
+            } else {
+                Err(CliError::Diagnostics(diagnostics))
+            }
+        }
+        "tokens" => {
+            let (file, content) = file_arg(command, rest)?;
+            for token in tokenize(&content, &file)? {
+                println!("{:?} {:?} at {:?}", token.type_, token.value, token.span);
+            }
+            Ok(())
+        }
+        "ast" => {
+            let (file, content) = file_arg(command, rest)?;
+            let (program, diagnostics) = Parser::with_file(&file).produce_ast_recovering(&content);
+            println!("{:#?}", program.body);
+            if diagnostics.is_empty() {
+                Ok(())
+            } else {
+                Err(CliError::Diagnostics(diagnostics))
+            }
+        }
+        "eval" => match rest {
+            [flag, code] if flag == "-e" => print_result(Interpreter::new().eval_source(code, "<eval>")?),
+            _ => Err(CliError::Usage("sun eval expects -e <code>".to_string())),
+        },
+        "repl" => {
+            repl::start();
+            Ok(())
+        }
+        "help" | "-h" | "--help" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        // `sun <file>` is shorthand for `sun run <file>`
+        path if (rest.is_empty() && !path.starts_with('-')) || path == "-" => cli(&["run".to_string(), path.to_string()]),
+        _ => Err(CliError::Usage(format!("Unknown command {}", command))),
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match cli(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(CliError::Usage(message)) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            ExitCode::from(EXIT_USAGE)
+        }
+        Err(CliError::Io(message)) => {
+            eprintln!("{}", message);
+            ExitCode::from(EXIT_IO_ERROR)
+        }
+        Err(CliError::Script(err)) => {
+            eprintln!("{}", err);
+            ExitCode::from(EXIT_SCRIPT_ERROR)
+        }
+        Err(CliError::Diagnostics(errors)) => {
+            for err in &errors {
+                eprintln!("{}\n", err);
+            }
+            eprintln!("{} error{} found", errors.len(), if errors.len() == 1 { "" } else { "s" });
+            ExitCode::from(EXIT_SCRIPT_ERROR)
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,96 @@
+use std::io::{self, Write};
+
+use sun::{tokenize, Interpreter, Parser, TokenType, Value};
+
+const FILE: &str = "<repl>";
+
+/// Interactive prompt sharing one global environment across inputs.
+pub fn start() {
+    let mut interpreter = Interpreter::new();
+    let mut buffer = String::new();
+
+    loop {
+        print!("{}", if buffer.is_empty() { "> " } else { "... " });
+        io::stdout().flush().ok();
+
+        // Lock stdin only while reading a line, so `input()` can read from it too
+        let mut line = String::new();
+        match io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        let line = line.trim_end_matches(['\n', '\r']);
+
+        if buffer.is_empty() {
+            if let Some(command) = line.trim().strip_prefix(':') {
+                if !meta_command(command, &interpreter) {
+                    break;
+                }
+                continue;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+        }
+
+        buffer.push_str(line);
+        buffer.push('\n');
+
+        // Keep reading while brackets are left open
+        if depth(&buffer) > 0 {
+            continue;
+        }
+
+        let source = std::mem::take(&mut buffer);
+        match interpreter.eval_source(&source, FILE) {
+            // Like the CLI, skip the nil that declarations and calls such as println return
+            Ok(Value::Nil) => {}
+            Ok(value) => println!("{}", value),
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+}
+
+/// Number of brackets still open in `source`. Lexer errors count as
+/// balanced so the input is submitted and the error reported.
+fn depth(source: &str) -> i32 {
+    let tokens = match tokenize(source, FILE) {
+        Ok(tokens) => tokens,
+        Err(_) => return 0,
+    };
+    tokens.iter().fold(0, |depth, token| match token.type_ {
+        TokenType::OpenBrace | TokenType::OpenParen | TokenType::OpenBracket => depth + 1,
+        TokenType::CloseBrace | TokenType::CloseParen | TokenType::CloseBracket => depth - 1,
+        _ => depth,
+    })
+}
+
+/// Runs `:command args`. Returns false when the REPL should exit.
+fn meta_command(command: &str, interpreter: &Interpreter) -> bool {
+    let (name, source) = command.split_once(' ').unwrap_or((command, ""));
+    match name {
+        "ast" => {
+            let (program, diagnostics) = Parser::with_file(FILE).produce_ast_recovering(source);
+            println!("{:#?}", program.body);
+            for err in diagnostics {
+                eprintln!("{}", err);
+            }
+        }
+        "tokens" => match tokenize(source, FILE) {
+            Ok(tokens) => {
+                for token in tokens {
+                    println!("{:?} {:?} at {:?}", token.type_, token.value, token.span);
+                }
+            }
+            Err(err) => eprintln!("{}", err),
+        },
+        "env" => {
+            for (name, value) in interpreter.globals() {
+                println!("{} = {}", name, value);
+            }
+        }
+        "quit" | "exit" => return false,
+        _ => eprintln!("Unknown command :{}. Available: :ast <code>, :tokens <code>, :env, :quit", name),
+    }
+    true
+}
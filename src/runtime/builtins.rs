@@ -0,0 +1,94 @@
+use std::cell::RefCell;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+use crate::environmment::Environmment;
+use crate::error::SunError;
+use crate::value::SunVariable;
+
+/// Streams the console built-ins use. Defaults to stdout and stdin;
+/// embedders can swap in their own to capture output or script input.
+///
+/// ```
+/// use std::{cell::RefCell, io::Cursor, rc::Rc};
+///
+/// let output = Rc::new(RefCell::new(Vec::new()));
+/// let input = Rc::new(RefCell::new(Cursor::new("world\n")));
+/// let mut sun = sun::Interpreter::with_console(sun::Console::new(output.clone()).with_input(input));
+/// sun.eval_str("println(\"hello\", input())").unwrap();
+/// assert_eq!(output.borrow().as_slice(), b"hello world\n");
+/// ```
+#[derive(Clone)]
+pub struct Console {
+    output: Rc<RefCell<dyn Write>>,
+    // `None` reads from stdin
+    input: Option<Rc<RefCell<dyn BufRead>>>,
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Console { output: Rc::new(RefCell::new(io::stdout())), input: None }
+    }
+}
+
+impl Console {
+    pub fn new(output: Rc<RefCell<dyn Write>>) -> Self {
+        Console { output, input: None }
+    }
+
+    pub fn with_input(mut self, input: Rc<RefCell<dyn BufRead>>) -> Self {
+        self.input = Some(input);
+        self
+    }
+
+    fn write(&self, text: &str) -> Result<(), SunError> {
+        let mut output = self.output.borrow_mut();
+        output.write_all(text.as_bytes())
+            .and_then(|_| output.flush())
+            .map_err(|err| SunError::native(format!("Cannot write output: {}", err)))
+    }
+
+    fn read_line(&self) -> Result<Option<String>, SunError> {
+        let mut line = String::new();
+        let read = match &self.input {
+            Some(input) => input.borrow_mut().read_line(&mut line),
+            None => io::stdin().read_line(&mut line),
+        }.map_err(|err| SunError::native(format!("Cannot read input: {}", err)))?;
+
+        if read == 0 {
+            return Ok(None);
+        }
+        let trimmed = line.trim_end_matches(['\n', '\r']).len();
+        line.truncate(trimmed);
+        Ok(Some(line))
+    }
+}
+
+fn join(arguments: &[SunVariable]) -> String {
+    arguments.iter().map(|argument| argument.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+/// Defines `print`, `println` and `input` in `env`.
+pub fn register_console(env: &Environmment, console: Console) {
+    let out = console.clone();
+    env.register_native("print", None, move |arguments| {
+        out.write(&join(arguments))?;
+        Ok(SunVariable::Nil)
+    });
+
+    let out = console.clone();
+    env.register_native("println", None, move |arguments| {
+        out.write(&(join(arguments) + "\n"))?;
+        Ok(SunVariable::Nil)
+    });
+
+    // input(prompt?) returns the next line without its newline, or nil at end of input
+    env.register_native("input", None, move |arguments| {
+        match arguments {
+            [] => {}
+            [prompt] => console.write(&prompt.to_string())?,
+            _ => return Err(SunError::native(format!("Expected at most 1 arguments but got {}", arguments.len()))),
+        }
+        Ok(console.read_line()?.map(SunVariable::string).unwrap_or_default())
+    });
+}
@@ -1,92 +1,319 @@
-use std::collections::HashMap;
-use std::vec::Vec;
-use crate::value::{self, *};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::{Rc, Weak};
+use crate::builtins::{register_console, Console};
+use crate::error::SunError;
+use crate::lexer::Span;
+use crate::value::*;
 
-#[derive(Clone, PartialEq)]
-pub struct Environmment<'a> {
-    parent: Option<&'a Environmment<'a>>,
+struct Scope {
+    parent: Option<Environmment>,
     variables: HashMap<String, SunVariable>,
-    constants: Vec<String>
+    constants: HashSet<String>
 }
 
-pub fn createGlobalEnv() -> Environmment<'static> {
-    let mut env = Environmment::new(None);
-    env.declare_var("true".to_string(), SunVariable::new().set_value(value::EnumVariableType::BOOLEAN, "true"), true, false);
-    env.declare_var("false".to_string(), SunVariable::new().set_value(value::EnumVariableType::BOOLEAN, "false"), true, false);
-    env.declare_var("nil".to_string(), SunVariable::new().set_value(value::EnumVariableType::NIL, ""), true, false);
+/// Handle to a scope in the environment chain. Cloning it is cheap and
+/// yields another handle to the same scope, so nested scopes and closures
+/// all see (and can mutate) the variables of their enclosing scopes.
+#[derive(Clone)]
+pub struct Environmment {
+    scope: Rc<RefCell<Scope>>
+}
+
+impl PartialEq for Environmment {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.scope, &other.scope)
+    }
+}
+
+pub fn createGlobalEnv() -> Environmment {
+    createGlobalEnvWithConsole(Console::default())
+}
+
+/// Global environment whose `print`, `println` and `input` use `console`.
+pub fn createGlobalEnvWithConsole(console: Console) -> Environmment {
+    let env = Environmment::new(None);
+    env.define("true".to_string(), SunVariable::Bool(true), true);
+    env.define("false".to_string(), SunVariable::Bool(false), true);
+    env.define("nil".to_string(), SunVariable::Nil, true);
+    register_console(&env, console);
     return env;
 }
 
-impl<'a> Environmment<'a> {
-    pub fn new(parent: Option<&'a Environmment<'a>>) -> Self {
-        let global = parent.is_some();
-        let mut env = Self {
-            parent: parent,
-            variables: HashMap::new(),
-            constants: Vec::new()
+impl Environmment {
+    pub fn new(parent: Option<&Environmment>) -> Self {
+        let env = Self {
+            scope: Rc::new(RefCell::new(Scope {
+                parent: parent.cloned(),
+                variables: HashMap::new(),
+                constants: HashSet::new()
+            }))
         };
-        if global {
-            env = createGlobalEnv()
+        let should_collect = SCOPES.with(|scopes| {
+            let mut scopes = scopes.borrow_mut();
+            scopes.scopes.push(Rc::downgrade(&env.scope));
+            scopes.scopes.len() >= scopes.threshold
+        });
+        if should_collect {
+            collect_cycles();
         }
-        
         env
     }
 
-    pub fn set_parent(&mut self, parent_env: &'a Environmment<'a>) {
-        self.parent = Some(parent_env);
+    pub fn parent(&self) -> Option<Environmment> {
+        self.scope.borrow().parent.clone()
     }
 
-    pub fn declare_var(&mut self, var_name: String, value: SunVariable, constant: bool, assingining: bool) -> SunVariable {
-        if !self.variables.contains_key(&var_name) {
-            let newvalue = value.clone();
-            self.variables.insert(var_name.clone(), newvalue);
-            if constant == true {
-                self.constants.insert(0, var_name);
-            }
-            return value;
+    pub(crate) fn define(&self, var_name: String, value: SunVariable, constant: bool) {
+        let mut scope = self.scope.borrow_mut();
+        if constant {
+            scope.constants.insert(var_name.clone());
         } else {
-            if assingining {
-                self.variables.remove(&var_name.clone());
-                return self.declare_var(var_name, value, constant, true);
+            scope.constants.remove(&var_name);
+        }
+        scope.variables.insert(var_name, value);
+    }
+
+    fn contains(&self, var_name: &str) -> bool {
+        self.scope.borrow().variables.contains_key(var_name)
+    }
+
+    /// Whether `var_name` is a constant declared directly in this scope.
+    pub fn is_constant(&self, var_name: &str) -> bool {
+        self.scope.borrow().constants.contains(var_name)
+    }
+
+    /// Exposes a Rust function to scripts under `name`. Calls with a
+    /// different number of arguments than `arity` fail before `function`
+    /// runs; pass `None` to accept any number.
+    pub fn register_native<F>(&self, name: &str, arity: Option<usize>, function: F)
+    where
+        F: Fn(&[SunVariable]) -> Result<SunVariable, SunError> + 'static,
+    {
+        let native = NativeFunction { name: name.to_string(), arity, function: Box::new(function) };
+        self.define(name.to_string(), SunVariable::native(native), false);
+    }
+
+    pub fn declare_var(&self, var_name: String, value: SunVariable, constant: bool, assingining: bool, span: &Span) -> Result<SunVariable, SunError> {
+        if self.contains(&var_name) && !assingining {
+            return Err(SunError::runtime(span, format!("Cannot declare variable {}. At is already is defined", var_name)));
+        }
+        self.define(var_name, value.clone(), constant);
+        return Ok(value);
+    }
+
+    pub fn look_up_var(&self, var_name: &str, span: &Span) -> Result<SunVariable, SunError> {
+        let env = self.resolve(var_name, span)?;
+        let scope = env.scope.borrow();
+        return Ok(scope.variables.get(var_name).cloned().unwrap_or_default());
+    }
+
+    /// Variables declared directly in this scope, sorted by name.
+    pub fn variables(&self) -> Vec<(String, SunVariable)> {
+        let scope = self.scope.borrow();
+        let mut variables: Vec<_> = scope.variables.iter().map(|(name, value)| (name.clone(), value.clone())).collect();
+        variables.sort_by(|a, b| a.0.cmp(&b.0));
+        variables
+    }
+
+    /// Updates `var_name` in the scope where it was declared.
+    pub fn assign_var(&self, var_name: &str, value: SunVariable, span: &Span) -> Result<SunVariable, SunError> {
+        let env = self.resolve(var_name, span)
+            .map_err(|_| SunError::runtime(span, format!("Cannot assign to {} as it was never declared", var_name)))?;
+        let mut scope = env.scope.borrow_mut();
+        if scope.constants.contains(var_name) {
+            return Err(SunError::runtime(span, format!("Cannot assign to {} as it is a constant", var_name)));
+        }
+        scope.variables.insert(var_name.to_string(), value.clone());
+        return Ok(value);
+    }
+
+    /// Finds the scope in the chain where `var_name` is declared.
+    pub fn resolve(&self, var_name: &str, span: &Span) -> Result<Environmment, SunError> {
+        let mut env = self.clone();
+        loop {
+            if env.contains(var_name) {
+                return Ok(env);
+            }
+            match env.parent() {
+                Some(parent) => env = parent,
+                None => return Err(SunError::runtime(span, format!("Cannot resolve {} as it does not exist", var_name))),
             }
-            println!("Cannot declare variable {}. At is already is defined", var_name);
-            std::process::exit(1);
         }
     }
+}
+
+/// Collect once this many scopes have been created since the last
+/// collection, or as many as survived it if that is more.
+const MIN_COLLECT_THRESHOLD: usize = 1024;
+
+/// Every scope created on this thread that may still be alive.
+struct ScopeRegistry {
+    scopes: Vec<Weak<RefCell<Scope>>>,
+    threshold: usize,
+}
+
+thread_local! {
+    static SCOPES: RefCell<ScopeRegistry> = const {
+        RefCell::new(ScopeRegistry { scopes: Vec::new(), threshold: MIN_COLLECT_THRESHOLD })
+    };
+}
 
-    pub fn look_up_var(mut self, var_name: String) -> SunVariable {
-        let mut env = self.resolve(var_name.clone());
-        if let Some(var) = env.variables.get(&var_name) {
-            return var.clone();
+/// A reference counted value the collector looks inside of.
+enum Node {
+    Scope(Rc<RefCell<Scope>>),
+    Function(Rc<SunFunction>),
+    Object(Rc<RefCell<Object>>),
+    Array(Rc<RefCell<Vec<SunVariable>>>),
+}
+
+impl Node {
+    fn from_value(value: &SunVariable) -> Option<Node> {
+        match value {
+            SunVariable::Function(function) => Some(Node::Function(function.clone())),
+            SunVariable::Object(object) => Some(Node::Object(object.clone())),
+            SunVariable::Array(elements) => Some(Node::Array(elements.clone())),
+            _ => None,
         }
-        
-        return SunVariable::new();
     }
 
-    /*pub fn assign_var(&mut self, var_name: String, value: SunVariable) -> SunVariable {
-        let mut env = self.clone().resolve(var_name.clone());
-        if self.constants.contains(&var_name) {
-            println!("cannot can change the {} value, is a constant", var_name);
-            std::process::exit(1);
+    fn ptr(&self) -> *const () {
+        match self {
+            Node::Scope(scope) => Rc::as_ptr(scope) as *const (),
+            Node::Function(function) => Rc::as_ptr(function) as *const (),
+            Node::Object(object) => Rc::as_ptr(object) as *const (),
+            Node::Array(elements) => Rc::as_ptr(elements) as *const (),
         }
-        env.variables.remove(&var_name.clone());
-        env.variables.insert(var_name.clone(), value.clone());
-        
-        return value;
-    }*/
-
-    pub fn resolve(self, var_name: String) -> Environmment<'a> {
-        if self.variables.contains_key(&var_name) {
-            return self;
-        } else if self.parent == None {
-            println!("Cannot resolve {} as it does not exist", var_name);
-            std::process::exit(1);
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Node::Scope(scope) => Rc::strong_count(scope),
+            Node::Function(function) => Rc::strong_count(function),
+            Node::Object(object) => Rc::strong_count(object),
+            Node::Array(elements) => Rc::strong_count(elements),
         }
-        
-        if let Some(parent) = self.parent {
-            return parent.clone().resolve(var_name);
-        } else {
-            return self.resolve(var_name);
+    }
+
+    /// Values this node holds a strong reference to, or `None` if it is
+    /// borrowed mutably right now.
+    fn children(&self) -> Option<Vec<Node>> {
+        let children = match self {
+            Node::Scope(scope) => {
+                let scope = scope.try_borrow().ok()?;
+                let parent = scope.parent.iter().map(|parent| Node::Scope(parent.scope.clone()));
+                parent.chain(scope.variables.values().filter_map(Node::from_value)).collect()
+            }
+            Node::Function(function) => vec![Node::Scope(function.closure.scope.clone())],
+            Node::Object(object) => object.try_borrow().ok()?.entries().filter_map(|(_, value)| Node::from_value(value)).collect(),
+            Node::Array(elements) => elements.try_borrow().ok()?.iter().filter_map(Node::from_value).collect(),
+        };
+        Some(children)
+    }
+}
+
+/// Frees scopes that are only kept alive by each other.
+///
+/// A function holds on to the scope it was defined in, and that scope
+/// usually holds the function, so a scope that defines a function is
+/// never freed by reference counting alone. This finds every scope,
+/// function, object and array reachable from a live scope, subtracts the
+/// references they hold to one another from their reference counts, and
+/// treats whatever is still referenced from elsewhere (an `Interpreter`,
+/// the host, or a call in progress) as live, together with everything
+/// reachable from it. The rest is garbage and is emptied, which drops
+/// the cycles. Live values are never touched, so this is safe to run
+/// with any number of interpreters on the thread.
+pub(crate) fn collect_cycles() {
+    let scopes: Vec<Rc<RefCell<Scope>>> = SCOPES.with(|scopes| {
+        let mut scopes = scopes.borrow_mut();
+        scopes.scopes.retain(|scope| scope.strong_count() > 0);
+        scopes.scopes.iter().filter_map(Weak::upgrade).collect()
+    });
+
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut index: HashMap<*const (), usize> = HashMap::new();
+    let mut edges: Vec<Vec<usize>> = Vec::new();
+
+    let mut pending: Vec<Node> = scopes.into_iter().map(Node::Scope).collect();
+    while let Some(node) = pending.pop() {
+        if index.contains_key(&node.ptr()) {
+            continue;
         }
+        index.insert(node.ptr(), nodes.len());
+        nodes.push(node);
     }
-}
\ No newline at end of file
+
+    let mut visited = 0;
+    while visited < nodes.len() {
+        // A value being mutated is in use; try again at the next collection
+        let children = match nodes[visited].children() {
+            Some(children) => children,
+            None => return reschedule(),
+        };
+        let mut targets = Vec::with_capacity(children.len());
+        for child in children {
+            let target = match index.get(&child.ptr()) {
+                Some(&target) => target,
+                None => {
+                    index.insert(child.ptr(), nodes.len());
+                    nodes.push(child);
+                    nodes.len() - 1
+                }
+            };
+            targets.push(target);
+        }
+        edges.push(targets);
+        visited += 1;
+    }
+
+    // References from outside the graph, not counting our own copy in `nodes`
+    let mut external: Vec<usize> = nodes.iter().map(|node| node.strong_count() - 1).collect();
+    for targets in &edges {
+        for &target in targets {
+            external[target] -= 1;
+        }
+    }
+
+    let mut live = vec![false; nodes.len()];
+    let mut pending: Vec<usize> = (0..nodes.len()).filter(|&node| external[node] > 0).collect();
+    while let Some(node) = pending.pop() {
+        if !live[node] {
+            live[node] = true;
+            pending.extend(&edges[node]);
+        }
+    }
+
+    // Empty the garbage, then drop what it held once nothing is borrowed
+    let mut freed: Vec<Box<dyn std::any::Any>> = Vec::new();
+    for (node, _) in nodes.iter().zip(&live).filter(|(_, live)| !**live) {
+        match node {
+            Node::Scope(scope) => {
+                let mut scope = scope.borrow_mut();
+                freed.push(Box::new(std::mem::take(&mut scope.variables)));
+                freed.push(Box::new(scope.parent.take()));
+                scope.constants.clear();
+            }
+            Node::Object(object) => freed.push(Box::new(std::mem::take(&mut *object.borrow_mut()))),
+            Node::Array(elements) => freed.push(Box::new(std::mem::take(&mut *elements.borrow_mut()))),
+            Node::Function(_) => {}
+        }
+    }
+    drop(freed);
+    drop(nodes);
+    reschedule();
+}
+
+fn reschedule() {
+    SCOPES.with(|scopes| {
+        let mut scopes = scopes.borrow_mut();
+        scopes.scopes.retain(|scope| scope.strong_count() > 0);
+        scopes.threshold = scopes.scopes.len() + scopes.scopes.len().max(MIN_COLLECT_THRESHOLD);
+    });
+}
+
+/// Number of scopes on this thread that are still alive.
+#[cfg(test)]
+pub(crate) fn live_scopes() -> usize {
+    SCOPES.with(|scopes| scopes.borrow().scopes.iter().filter(|scope| scope.strong_count() > 0).count())
+}
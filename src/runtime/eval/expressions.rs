@@ -1,81 +1,301 @@
-use crate::{ast::{AssignmentExpr, BinaryExpr, Expr, Identifier, NodeType, ObjectLiteral, Stmt, VarDeclaration}, environmment::{self, Environmment}, interpreter::evaluate, runtime, value::{EnumVariableType, Object, SunVariable}};
+use std::cmp::Ordering;
+use std::rc::Rc;
 
-pub fn eval_numeric_binary_expr(lhs: SunVariable, rhs: SunVariable, operator: String, env: &Environmment) -> SunVariable {
-    let mut result = 0.0;
-    
-    if operator == "+" {
-        result = lhs.get_number() + rhs.get_number();
+use crate::{error::SunError, ast::{ArrayLiteral, AssignmentExpr, BinaryExpr, CallExpr, Expr, Function, Identifier, MemberExpr, ObjectLiteral, UnaryExpr, VarDeclaration}, environmment::Environmment, interpreter::{evaluate_expr, Flow}, value::{Object, SunFunction, SunVariable}};
+use std::cell::RefCell;
+
+use super::{stack_left_below, CALL_RED_ZONE};
+use super::statements::eval_body;
+
+pub fn eval_numeric_binary_expr(lhs: f64, rhs: f64, operator: &str, _env: &Environmment) -> SunVariable {
+    let result = if operator == "+" {
+        lhs + rhs
     } else if operator == "-" {
-        result = lhs.get_number() - rhs.get_number();
+        lhs - rhs
     } else if operator == "*" {
-        result = lhs.get_number() * rhs.get_number();
+        lhs * rhs
     } else if operator == "/" {
-        result = lhs.get_number() / rhs.get_number();
+        lhs / rhs
     } else {
-        result = lhs.get_number() % rhs.get_number();
-    }
+        lhs % rhs
+    };
     
-    return SunVariable::new().set_value(EnumVariableType::NUMBER, format!("{}", result));
+    return SunVariable::Number(result);
 }
 
-pub fn eval_binary_expr(Binop: &BinaryExpr, env: &mut Environmment) -> SunVariable {
-    let left: &Box<dyn Expr> = &Binop.left;
-    let right = &Binop.right;
-    
+fn boolean(value: bool) -> SunVariable {
+    SunVariable::Bool(value)
+}
+
+pub fn eval_comparison_binary_expr(lhs: SunVariable, rhs: SunVariable, Binop: &BinaryExpr) -> Result<SunVariable, SunError> {
+    let ordering = match (&lhs, &rhs) {
+        (SunVariable::Number(left), SunVariable::Number(right)) => left.partial_cmp(right),
+        (SunVariable::String(left), SunVariable::String(right)) => Some(left.cmp(right)),
+        (SunVariable::Bool(left), SunVariable::Bool(right)) => Some(left.cmp(right)),
+        (SunVariable::Nil, SunVariable::Nil) => Some(Ordering::Equal),
+        (left, right) => {
+            return Err(SunError::runtime(&Binop.span, format!("Cannot compare {:?} with {:?} using {}", left.get_type(), right.get_type(), Binop.operator)));
+        }
+    };
+
+    // NaN is unordered, so every comparison against it is false
+    let result = match ordering {
+        Some(ordering) => match Binop.operator.as_str() {
+            "<" => ordering == Ordering::Less,
+            "<=" => ordering != Ordering::Greater,
+            ">" => ordering == Ordering::Greater,
+            _ => ordering != Ordering::Less,
+        },
+        None => false,
+    };
+
+    return Ok(boolean(result));
+}
+
+pub fn eval_logical_expr(Binop: &BinaryExpr, env: &Environmment) -> Result<SunVariable, SunError> {
+    let lhs = evaluate_expr(&Binop.left, env)?.is_truthy();
+
+    // Curto-circuito: o lado direito só é avaliado quando necessário
+    if (Binop.operator == "&&" && !lhs) || (Binop.operator == "||" && lhs) {
+        return Ok(boolean(lhs));
+    }
+
+    return Ok(boolean(evaluate_expr(&Binop.right, env)?.is_truthy()));
+}
+
+pub fn eval_binary_expr(Binop: &BinaryExpr, env: &Environmment) -> Result<SunVariable, SunError> {
+    if Binop.operator == "&&" || Binop.operator == "||" {
+        return eval_logical_expr(Binop, env);
+    }
+
     // Avaliando os lados esquerdo e direito da expressão binária
-    let lhs = evaluate(left.as_stmt(), env);
-    let rhs = evaluate(right.as_stmt(), env);
-    
-    if lhs.get_type() == &EnumVariableType::NUMBER && rhs.get_type() == &EnumVariableType::NUMBER {
-        return eval_numeric_binary_expr(lhs, rhs, Binop.operator.clone(), env);
+    let lhs = evaluate_expr(&Binop.left, env)?;
+    let rhs = evaluate_expr(&Binop.right, env)?;
+
+    match Binop.operator.as_str() {
+        "==" => return Ok(boolean(lhs == rhs)),
+        "!=" => return Ok(boolean(lhs != rhs)),
+        "<" | "<=" | ">" | ">=" => return eval_comparison_binary_expr(lhs, rhs, Binop),
+        _ => {}
     }
     
-    return SunVariable::new().set_value(EnumVariableType::NIL, "");
+    match (&lhs, &rhs) {
+        (SunVariable::Number(left), SunVariable::Number(right)) => {
+            Ok(eval_numeric_binary_expr(*left, *right, &Binop.operator, env))
+        }
+        (SunVariable::String(left), SunVariable::String(right)) if Binop.operator == "+" => {
+            Ok(SunVariable::string(format!("{}{}", left, right)))
+        }
+        (left, right) => Err(SunError::runtime(&Binop.span, format!(
+            "Cannot apply {} to {:?} and {:?}", Binop.operator, left.get_type(), right.get_type()
+        ))),
+    }
 }
 
-pub fn eval_identifier(iden: &Identifier, env: &mut Environmment) -> SunVariable {
-    let val = env.clone().look_up_var(iden.symbol.clone());
-    return val;
+fn type_name(value: &SunVariable) -> &'static str {
+    match value {
+        SunVariable::Nil => "nil",
+        SunVariable::Number(_) => "number",
+        SunVariable::Bool(_) => "boolean",
+        SunVariable::String(_) => "string",
+        SunVariable::Object(_) => "object",
+        SunVariable::Array(_) => "array",
+        SunVariable::Function(_) | SunVariable::Native(_) => "function",
+    }
 }
 
-pub fn eval_object_expr(obj: &ObjectLiteral, env: &mut Environmment) -> SunVariable {
-    let mut object = SunVariable::new().set_value(EnumVariableType::OBJECT, "");
-    
-    for value in &obj.value {
-        if let Some(val) = value.1.value.as_ref() {
-            let mut runtime_val = if value.1.value.is_none() {
-                env.clone().look_up_var(value.0.to_string())
-            } else {
-                evaluate(val.as_stmt(), env)
-            };
-            
-            object.get_object().add_variable(value.0.to_string(), runtime_val);
+pub fn eval_unary_expr(unary: &UnaryExpr, env: &Environmment) -> Result<SunVariable, SunError> {
+    let argument = evaluate_expr(&unary.argument, env)?;
+
+    match unary.operator.as_str() {
+        "!" => Ok(boolean(!argument.is_truthy())),
+        "-" | "+" => match argument {
+            SunVariable::Number(number) if unary.operator == "-" => Ok(SunVariable::Number(-number)),
+            SunVariable::Number(number) => Ok(SunVariable::Number(number)),
+            other => Err(SunError::runtime(&unary.span, format!("Unary {} expects a number, got {:?}", unary.operator, other.get_type()))),
+        },
+        "typeof" => Ok(SunVariable::string(type_name(&argument))),
+        operator => Err(SunError::runtime(&unary.span, format!("Unknown unary operator {}", operator))),
+    }
+}
+
+pub fn eval_function_expr(function: &Function, env: &Environmment) -> SunVariable {
+    SunVariable::function(SunFunction { declaration: function.clone(), closure: env.clone() })
+}
+
+fn check_arity(call: &CallExpr, expected: usize) -> Result<(), SunError> {
+    if call.arguments.len() != expected {
+        return Err(SunError::runtime(&call.span, format!(
+            "Expected {} arguments but got {}", expected, call.arguments.len()
+        )));
+    }
+    Ok(())
+}
+
+pub fn eval_call_expr(call: &CallExpr, env: &Environmment) -> Result<SunVariable, SunError> {
+    let callee = evaluate_expr(&call.callee, env)?;
+
+    if let SunVariable::Native(native) = &callee {
+        if let Some(arity) = native.arity {
+            check_arity(call, arity)?;
         }
+        let mut arguments = Vec::with_capacity(call.arguments.len());
+        for argument in &call.arguments {
+            arguments.push(evaluate_expr(argument, env)?);
+        }
+        return (native.function)(&arguments).map_err(|err| err.or_span(&call.span));
     }
-    
-    object
+
+    let function = match &callee {
+        SunVariable::Function(function) => function,
+        _ => return Err(SunError::runtime(call.callee.span(), format!("Cannot call a value of type {:?}", callee.get_type()))),
+    };
+
+    let declaration = &function.declaration;
+    check_arity(call, declaration.parameters.len())?;
+
+    let mut arguments = Vec::with_capacity(call.arguments.len());
+    for argument in &call.arguments {
+        arguments.push(evaluate_expr(argument, env)?);
+    }
+
+    if stack_left_below(CALL_RED_ZONE) {
+        return Err(SunError::runtime(&call.span, "Maximum call depth exceeded"));
+    }
+    let scope = Environmment::new(Some(&function.closure));
+    for (parameter, argument) in declaration.parameters.iter().zip(arguments) {
+        scope.declare_var(parameter.clone(), argument, false, true, &call.span)?;
+    }
+
+    return match eval_body(&declaration.body, &scope)? {
+        Flow::Return(value) => Ok(value),
+        Flow::Value(_) => Ok(SunVariable::Nil),
+    };
 }
 
+pub fn eval_identifier(iden: &Identifier, env: &Environmment) -> Result<SunVariable, SunError> {
+    env.look_up_var(&iden.symbol, &iden.span)
+}
+
+/// Location that `obj.key` / `obj[key]` refers to.
+enum MemberTarget {
+    Property(Rc<RefCell<Object>>, String),
+    Element(Rc<RefCell<Vec<SunVariable>>>, usize),
+    Length(usize),
+}
 
-pub fn eval_var_declaration(vardec: &VarDeclaration, env: &mut Environmment) -> SunVariable {
-    if let Some(Value) = &vardec.value {
-        let value = evaluate(Value.as_stmt(), env);
-        env.declare_var(vardec.identifier.clone(), value, vardec.constant, false);
+fn eval_member_target(member: &MemberExpr, env: &Environmment) -> Result<MemberTarget, SunError> {
+    let object = evaluate_expr(&member.object, env)?;
+    let key = match (&*member.property, member.computed) {
+        (Expr::Identifier(iden), false) => SunVariable::string(iden.symbol.as_str()),
+        (property, _) => evaluate_expr(property, env)?,
+    };
+    let key_span = member.property.span();
+
+    if let SunVariable::Array(elements) = object {
+        return match key {
+            SunVariable::Number(index) => {
+                if index < 0.0 {
+                    Err(SunError::runtime(key_span, format!("Array index cannot be negative, got {}", index)))
+                } else if index.fract() != 0.0 || !index.is_finite() {
+                    Err(SunError::runtime(key_span, format!("Array index must be an integer, got {}", index)))
+                } else {
+                    Ok(MemberTarget::Element(elements, index as usize))
+                }
+            }
+            SunVariable::String(name) if &*name == "length" => Ok(MemberTarget::Length(elements.borrow().len())),
+            SunVariable::String(name) => Err(SunError::runtime(key_span, format!("Arrays have no property {}", name))),
+            other => Err(SunError::runtime(key_span, format!("Cannot index an array with a value of type {:?}", other.get_type()))),
+        };
     }
-    SunVariable::new()
+
+    let key = match key {
+        SunVariable::String(name) => name.to_string(),
+        SunVariable::Number(number) => number.to_string(),
+        other => return Err(SunError::runtime(key_span, format!("Cannot use a value of type {:?} as a property key", other.get_type()))),
+    };
+
+    match object {
+        SunVariable::Object(data) => Ok(MemberTarget::Property(data, key)),
+        _ => Err(SunError::runtime(member.object.span(), format!("Cannot access property {} of {:?}", key, object.get_type()))),
+    }
+}
+
+pub fn eval_member_expr(member: &MemberExpr, env: &Environmment) -> Result<SunVariable, SunError> {
+    let value = match eval_member_target(member, env)? {
+        MemberTarget::Property(object, key) => object.borrow().get_variable(&key),
+        MemberTarget::Element(elements, index) => elements.borrow().get(index).cloned(),
+        MemberTarget::Length(length) => Some(SunVariable::Number(length as f64)),
+    };
+    Ok(value.unwrap_or_default())
 }
 
-pub fn eval_assingment(node: &AssignmentExpr, env: &mut Environmment) -> SunVariable {
-    if node.assgine.get_kind() != NodeType::Identifier {
-        println!("Invalide LHS inaide assingment Expr {:#?}", node.assgine);
-        std::process::exit(1);
+fn assign_member(member: &MemberExpr, value: SunVariable, env: &Environmment) -> Result<SunVariable, SunError> {
+    match eval_member_target(member, env)? {
+        MemberTarget::Property(object, key) => {
+            object.borrow_mut().add_variable(key, value.clone());
+        }
+        MemberTarget::Element(elements, index) => {
+            let mut elements = elements.borrow_mut();
+            // Atribuir ao índice logo após o último elemento adiciona um novo elemento
+            if index < elements.len() {
+                elements[index] = value.clone();
+            } else if index == elements.len() {
+                elements.push(value.clone());
+            } else {
+                return Err(SunError::runtime(&member.span, format!("Index {} out of bounds for array of length {}", index, elements.len())));
+            }
+        }
+        MemberTarget::Length(_) => {
+            return Err(SunError::runtime(&member.span, "Cannot assign to the length of an array"));
+        }
     }
-    let mut varname = String::new();
-    varname = "j".to_string();
-    if let Some(iden) = node.as_identifier() {
-        varname = iden.symbol.clone();
+    Ok(value)
+}
+
+pub fn eval_array_expr(array: &ArrayLiteral, env: &Environmment) -> Result<SunVariable, SunError> {
+    let mut elements = Vec::with_capacity(array.elements.len());
+    for element in &array.elements {
+        elements.push(evaluate_expr(element, env)?);
+    }
+    Ok(SunVariable::array(elements))
+}
+
+pub fn eval_object_expr(obj: &ObjectLiteral, env: &Environmment) -> Result<SunVariable, SunError> {
+    let mut object = Object::new();
+    
+    for property in &obj.properties {
+        let runtime_val = match &property.value {
+            Some(value) => evaluate_expr(value, env)?,
+            None => env.look_up_var(&property.key, &property.span)?,
+        };
+        
+        object.add_variable(property.key.clone(), runtime_val);
     }
-    let value = evaluate(node.value.as_stmt(), &mut env.clone());
     
-    return env.declare_var(varname, value, true, true);
+    Ok(SunVariable::object(object))
+}
+
+
+pub fn eval_var_declaration(vardec: &VarDeclaration, env: &Environmment) -> Result<SunVariable, SunError> {
+    let value = match &vardec.value {
+        Some(Value) => evaluate_expr(Value, env)?,
+        None => SunVariable::Nil,
+    };
+    env.declare_var(vardec.identifier.clone(), value, vardec.constant, false, &vardec.span)?;
+    Ok(SunVariable::Nil)
+}
+
+pub fn eval_assingment(node: &AssignmentExpr, env: &Environmment) -> Result<SunVariable, SunError> {
+    match &*node.assgine {
+        Expr::Identifier(iden) => {
+            let value = evaluate_expr(&node.value, env)?;
+            return env.assign_var(&iden.symbol, value, &iden.span);
+        }
+        Expr::Member(member) => {
+            let value = evaluate_expr(&node.value, env)?;
+            return assign_member(member, value, env);
+        }
+        other => Err(SunError::runtime(other.span(), "Invalide LHS inaide assingment Expr")),
+    }
 }
\ No newline at end of file
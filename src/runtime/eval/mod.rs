@@ -1,2 +1,17 @@
 pub mod expressions;
-pub mod statements;
\ No newline at end of file
+pub mod statements;
+
+/// Native stack a user function call has to leave free: enough for its
+/// body up to the next call, and for any built-ins it calls.
+pub const CALL_RED_ZONE: usize = 128 * 1024;
+
+/// Native stack evaluating a nested statement or expression has to leave
+/// free. Smaller than `CALL_RED_ZONE`, so runaway recursion is reported
+/// by the call that goes too deep rather than by what it was evaluating.
+pub const NESTING_RED_ZONE: usize = 64 * 1024;
+
+/// Whether less than `red_zone` bytes of native stack are left. Asks the
+/// OS, so it adapts to the thread and build profile.
+pub fn stack_left_below(red_zone: usize) -> bool {
+    stacker::remaining_stack().is_some_and(|remaining| remaining < red_zone)
+}
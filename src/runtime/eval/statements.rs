@@ -1,11 +1,68 @@
-use crate::{ast::Program, environmment::Environmment, interpreter::evaluate, value::SunVariable};
+use crate::{error::SunError, ast::{BlockStmt, Function, IfStmt, Program, ReturnStmt, Stmt, WhileStmt}, environmment::Environmment, interpreter::{evaluate, evaluate_expr, execute, Flow}, value::SunVariable};
 
-pub fn eval_program(program: &Program, env: &mut Environmment) -> SunVariable {
-    let mut lastEvaluated = SunVariable::new();
+use super::expressions::eval_function_expr;
+
+pub fn eval_program(program: &Program, env: &Environmment) -> Result<SunVariable, SunError> {
+    let mut lastEvaluated = SunVariable::Nil;
     
     for statement in &program.body {
-        lastEvaluated = evaluate(&**statement, env);
+        lastEvaluated = evaluate(statement, env)?;
     }
     
-    return lastEvaluated
-}
\ No newline at end of file
+    return Ok(lastEvaluated);
+}
+
+/// Runs `body` in `env`, stopping early if a statement returns.
+pub fn eval_body(body: &[Stmt], env: &Environmment) -> Result<Flow, SunError> {
+    let mut lastEvaluated = SunVariable::Nil;
+
+    for statement in body {
+        match execute(statement, env)? {
+            Flow::Value(value) => lastEvaluated = value,
+            Flow::Return(value) => return Ok(Flow::Return(value)),
+        }
+    }
+
+    return Ok(Flow::Value(lastEvaluated));
+}
+
+pub fn eval_block_stmt(block: &BlockStmt, env: &Environmment) -> Result<Flow, SunError> {
+    let scope = Environmment::new(Some(env));
+    return eval_body(&block.body, &scope);
+}
+
+pub fn eval_if_stmt(if_stmt: &IfStmt, env: &Environmment) -> Result<Flow, SunError> {
+    if evaluate_expr(&if_stmt.condition, env)?.is_truthy() {
+        return execute(&if_stmt.then_branch, env);
+    } else if let Some(else_branch) = &if_stmt.else_branch {
+        return execute(else_branch, env);
+    }
+
+    return Ok(Flow::Value(SunVariable::Nil));
+}
+
+pub fn eval_while_stmt(while_stmt: &WhileStmt, env: &Environmment) -> Result<Flow, SunError> {
+    while evaluate_expr(&while_stmt.condition, env)?.is_truthy() {
+        if let Flow::Return(value) = execute(&while_stmt.body, env)? {
+            return Ok(Flow::Return(value));
+        }
+    }
+
+    return Ok(Flow::Value(SunVariable::Nil));
+}
+
+pub fn eval_function_declaration(function: &Function, env: &Environmment) -> Result<SunVariable, SunError> {
+    let value = eval_function_expr(function, env);
+    let name = function.name.clone().unwrap_or_default();
+    env.declare_var(name, value, false, false, &function.span)?;
+    return Ok(SunVariable::Nil);
+}
+
+pub fn eval_return_stmt(return_stmt: &ReturnStmt, env: &Environmment) -> Result<Flow, SunError> {
+    let value = match &return_stmt.value {
+        Some(value) => evaluate_expr(value, env)?,
+        None => SunVariable::Nil,
+    };
+
+    return Ok(Flow::Return(value));
+}
@@ -1,79 +1,392 @@
-use crate::ast::{self, *};
+use crate::ast::*;
+use crate::builtins::Console;
+use crate::error::SunError;
 use crate::runtime::value::*;
-use crate::environmment::{self, *};
+use crate::environmment::*;
 use crate::eval::*;
-use std::fmt::format;
-use std::ops::Deref;
-use std::{any, vec};
-use std::rc::Rc;
-
-fn errorN(Char: &str) {
-    println!("Stmt not reconized: {}", Char);
-    std::process::exit(1);
+use crate::eval::statements::eval_program;
+use crate::lexer::Span;
+use crate::parse::Parser;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Outcome of running a statement: its value, or a `return` unwinding
+/// to the enclosing function call.
+pub enum Flow {
+    Value(SunVariable),
+    Return(SunVariable),
 }
 
-pub fn evaluate(astNode: &dyn Stmt, env: &mut Environmment) -> SunVariable {
-    match astNode.get_kind() {
-        NodeType::NumericLiteral => {
-            if let Some(numeric_literal) = astNode.as_numeric_literal() {
-                return SunVariable::new().set_value(EnumVariableType::NUMBER, format!("{}", numeric_literal.value));
-            } else {
-                errorN(&format!("{:#?}", astNode).to_string());
-                return SunVariable::new().set_value(EnumVariableType::NIL, "");
-            }
+impl Flow {
+    pub fn into_value(self) -> SunVariable {
+        match self {
+            Flow::Value(value) | Flow::Return(value) => value,
         }
-        NodeType::BinaryExpr => {
-            if let Some(binary_expr) = astNode.as_binary_expr() {
-                return expressions::eval_binary_expr(binary_expr, env);
-            } else {
-                errorN(&format!("{:#?}", astNode).to_string());
-                return SunVariable::new().set_value(EnumVariableType::NIL, "");
-            }
+    }
+}
+
+pub fn execute(astNode: &Stmt, env: &Environmment) -> Result<Flow, SunError> {
+    if stack_left_below(NESTING_RED_ZONE) {
+        return Err(SunError::runtime(astNode.span(), "Expression nested too deeply"));
+    }
+    match astNode {
+        Stmt::VarDeclaration(vardeclaration) => Ok(Flow::Value(expressions::eval_var_declaration(vardeclaration, env)?)),
+        Stmt::If(if_stmt) => statements::eval_if_stmt(if_stmt, env),
+        Stmt::While(while_stmt) => statements::eval_while_stmt(while_stmt, env),
+        Stmt::Block(block) => statements::eval_block_stmt(block, env),
+        Stmt::FunctionDeclaration(function) => Ok(Flow::Value(statements::eval_function_declaration(function, env)?)),
+        Stmt::Return(return_stmt) => statements::eval_return_stmt(return_stmt, env),
+        Stmt::Expr(expr) => Ok(Flow::Value(evaluate_expr(expr, env)?)),
+        Stmt::Error(error) => Err(SunError::runtime(&error.span, "Cannot run code that failed to parse")),
+    }
+}
+
+pub fn evaluate(astNode: &Stmt, env: &Environmment) -> Result<SunVariable, SunError> {
+    Ok(execute(astNode, env)?.into_value())
+}
+
+pub fn evaluate_expr(expr: &Expr, env: &Environmment) -> Result<SunVariable, SunError> {
+    if stack_left_below(NESTING_RED_ZONE) {
+        return Err(SunError::runtime(expr.span(), "Expression nested too deeply"));
+    }
+    match expr {
+        Expr::NumericLiteral(numeric_literal) => {
+            Ok(SunVariable::Number(numeric_literal.value))
         }
-        NodeType::Identifier => {
-            if let Some(identifier) = astNode.as_identifier() {
-                return expressions::eval_identifier(identifier, env);
-            } else {
-                errorN(&format!("{:#?}", astNode).to_string());
-                return SunVariable::new().set_value(EnumVariableType::NIL, "");
-            }
+        Expr::StringLiteral(string_literal) => {
+            Ok(SunVariable::string(string_literal.value.as_str()))
         }
-        NodeType::ObjectLiteral => {
-            if let Some(obj) = astNode.as_object_literal() {
-                return  expressions::eval_object_expr(obj, env);
-            } else {
-                errorN("");
-                return  SunVariable::new().set_value(EnumVariableType::NIL, "");
-            }
+        Expr::Binary(binary_expr) => expressions::eval_binary_expr(binary_expr, env),
+        Expr::Unary(unary_expr) => expressions::eval_unary_expr(unary_expr, env),
+        Expr::Call(call_expr) => expressions::eval_call_expr(call_expr, env),
+        Expr::Member(member_expr) => expressions::eval_member_expr(member_expr, env),
+        Expr::Function(function) => Ok(expressions::eval_function_expr(function, env)),
+        Expr::Identifier(identifier) => expressions::eval_identifier(identifier, env),
+        Expr::ObjectLiteral(obj) => expressions::eval_object_expr(obj, env),
+        Expr::ArrayLiteral(array) => expressions::eval_array_expr(array, env),
+        Expr::Assignment(asn) => expressions::eval_assingment(asn, env),
+        Expr::Error(error) => Err(SunError::runtime(&error.span, "Cannot run code that failed to parse")),
+    }
+}
+
+/// Embedding entry point: a global environment that successive calls to
+/// `eval_str` / `eval_file` run in, so declarations persist between them.
+///
+/// Dropping the interpreter frees its globals even though the functions
+/// declared among them refer back to the global scope (see
+/// `collect_cycles`). Functions the host still holds keep working.
+pub struct Interpreter {
+    env: Environmment,
+}
+
+impl Drop for Interpreter {
+    fn drop(&mut self) {
+        // Let go of the globals first so they can be collected
+        self.env = Environmment::new(None);
+        collect_cycles();
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Interpreter { env: createGlobalEnv() }
+    }
+
+    /// Interpreter whose console built-ins use `console` instead of stdout/stdin.
+    pub fn with_console(console: Console) -> Self {
+        Interpreter { env: createGlobalEnvWithConsole(console) }
+    }
+
+    pub fn eval_str(&mut self, source: &str) -> Result<SunVariable, SunError> {
+        self.eval_source(source, "<input>")
+    }
+
+    /// Runs `source`, reporting error positions inside `file`.
+    pub fn eval_source(&mut self, source: &str, file: &str) -> Result<SunVariable, SunError> {
+        let program = Parser::with_file(file).produce_ast(source)?;
+        eval_program(&program, &self.env)
+    }
+
+    pub fn eval_file(&mut self, path: impl AsRef<Path>) -> Result<SunVariable, SunError> {
+        let (name, source) = read_script(path)?;
+        self.eval_source(&source, &name)
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<SunVariable> {
+        self.env.look_up_var(name, &Span::default()).ok()
+    }
+
+    /// Declares or overwrites a global variable. Fails if `name` is a
+    /// constant such as `true`, so scripts can rely on it.
+    pub fn set_global(&mut self, name: &str, value: SunVariable) -> Result<(), SunError> {
+        self.check_not_constant(name)?;
+        self.env.define(name.to_string(), value, false);
+        Ok(())
+    }
+
+    /// Exposes a Rust function to scripts, see `set_global`.
+    pub fn register_native<F>(&mut self, name: &str, arity: Option<usize>, function: F) -> Result<(), SunError>
+    where
+        F: Fn(&[SunVariable]) -> Result<SunVariable, SunError> + 'static,
+    {
+        self.check_not_constant(name)?;
+        self.env.register_native(name, arity, function);
+        Ok(())
+    }
+
+    fn check_not_constant(&self, name: &str) -> Result<(), SunError> {
+        if self.env.is_constant(name) {
+            return Err(SunError::native(format!("Cannot assign to {} as it is a constant", name)));
         }
-        NodeType::VarDeclarationStmt => {
-            if let Some(vardeclaration) = astNode.as_var_declaration() {
-                return expressions::eval_var_declaration(&vardeclaration, env);
-            } else {
-                errorN(&format!("{:#?}", astNode).to_string());
-                return SunVariable::new().set_value(EnumVariableType::NIL, "");
-            }
+        Ok(())
+    }
+
+    /// The global variables scripts have declared, sorted by name.
+    pub fn globals(&self) -> Vec<(String, SunVariable)> {
+        self.env.variables()
+    }
+}
+
+/// Reads the script at `path`. Returns the name to report positions
+/// under along with the source.
+pub fn read_script(path: impl AsRef<Path>) -> Result<(String, String), SunError> {
+    let path = path.as_ref();
+    let name = path.display().to_string();
+    let source = fs::read_to_string(path).map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => SunError::io(&name, format!("File not found: {}", name)),
+        _ => SunError::io(&name, format!("Cannot read {}: {}", name, err)),
+    })?;
+    Ok((name, source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environmment::live_scopes;
+
+    fn run(source: &str) -> SunVariable {
+        Interpreter::new().eval_str(source).unwrap_or_else(|err| panic!("{}: {}", source, err))
+    }
+
+    fn show(source: &str) -> String {
+        run(source).to_string()
+    }
+
+    fn error(source: &str) -> String {
+        match Interpreter::new().eval_str(source) {
+            Ok(value) => panic!("{}: expected an error, got {}", source, value),
+            Err(err) => err.message().to_string(),
         }
-        NodeType::AssignmentExpr => {
-            if let Some(asn) = astNode.as_assignment_expr() {
-                return expressions::eval_assingment(asn, env);
-            } else {
-                errorN("abc");
-                return SunVariable::new();
+    }
+
+    #[test]
+    fn control_flow_and_blocks() {
+        let source = "let i = 0; let s = 0;
+            while (i < 5) {
+                i = i + 1;
+                if (i % 2 == 0) { s = s + i; } else if (i == 5) { s = s + 100; } else { }
             }
+            s";
+        assert_eq!(run(source), SunVariable::Number(106.0));
+        assert_eq!(run("let x = 1; { let x = 2; } x"), SunVariable::Number(1.0));
+        assert_eq!(error("{ let inner = 1; } inner"), "Cannot resolve inner as it does not exist");
+    }
+
+    #[test]
+    fn assignment() {
+        assert_eq!(run("let x = 1; { x = 2; } x"), SunVariable::Number(2.0));
+        assert_eq!(run("let x = 1; fn set() { x = 5; } set(); x"), SunVariable::Number(5.0));
+        assert_eq!(error("const c = 1; c = 2;"), "Cannot assign to c as it is a constant");
+        assert_eq!(error("true = 1;"), "Cannot assign to true as it is a constant");
+        assert_eq!(error("y = 3;"), "Cannot assign to y as it was never declared");
+        assert_eq!(error("let v = 1; let v = 2;"), "Cannot declare variable v. At is already is defined");
+
+        let mut sun = Interpreter::new();
+        assert!(sun.eval_str("const c = 1; c = 2;").is_err());
+        assert_eq!(sun.eval_str("c").unwrap(), SunVariable::Number(1.0));
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(show("1 + 2 * 3 % 4"), "3");
+        assert_eq!(show("\"a\" + \"b\""), "ab");
+        assert_eq!(run("1 < 2 && \"a\" < \"b\" && nil == nil && 1 != 2"), SunVariable::Bool(true));
+        assert_eq!(run("1 == \"1\" || [1] == [1]"), SunVariable::Bool(false));
+        assert_eq!(error("1 < \"a\""), "Cannot compare NUMBER with STRING using <");
+
+        let calls = "let n = 0; fn f() { n = n + 1; return true; }";
+        assert_eq!(run(&format!("{} false && f(); true || f(); n", calls)), SunVariable::Number(0.0));
+        assert_eq!(run(&format!("{} true && f(); false || f(); n", calls)), SunVariable::Number(2.0));
+    }
+
+    #[test]
+    fn unary_operators() {
+        assert_eq!(show("-2 * 3"), "-6");
+        assert_eq!(show("-(1 + 2)"), "-3");
+        assert_eq!(show("- -2 + +1"), "3");
+        assert_eq!(run("!nil && !0 && !\"\" && !!true"), SunVariable::Bool(true));
+        assert_eq!(error("-\"x\""), "Unary - expects a number, got STRING");
+        assert_eq!(error("+\"x\""), "Unary + expects a number, got STRING");
+
+        let types = "[typeof 1, typeof \"s\", typeof true, typeof nil, typeof [], typeof {}, typeof fn() {}, typeof println, typeof typeof 1]";
+        assert_eq!(show(types), r#"["number", "string", "boolean", "nil", "array", "object", "function", "function", "string"]"#);
+    }
+
+    #[test]
+    fn functions_and_closures() {
+        let counter = "fn make_counter() { let count = 0; return fn() { count = count + 1; return count; }; }
+            let a = make_counter(); let b = make_counter();";
+        assert_eq!(run(&format!("{} a(); a(); b(); a()", counter)), SunVariable::Number(3.0));
+        assert_eq!(run(&format!("{} a(); a(); b(); b()", counter)), SunVariable::Number(2.0));
+        assert_eq!(run("fn add(a, b) { return a + b; } add(1, 2)"), SunVariable::Number(3.0));
+        assert_eq!(run("fn nothing() { } nothing()"), SunVariable::Nil);
+        assert_eq!(error("(fn(a) { return a; })()"), "Expected 1 arguments but got 0");
+        assert_eq!(error("let x = 1; x()"), "Cannot call a value of type NUMBER");
+    }
+
+    #[test]
+    fn natives() {
+        let mut sun = Interpreter::new();
+        sun.register_native("add", Some(2), |arguments| match arguments {
+            [SunVariable::Number(a), SunVariable::Number(b)] => Ok(SunVariable::Number(a + b)),
+            _ => Err(SunError::native("add expects numbers")),
+        }).unwrap();
+        assert_eq!(sun.eval_str("add(1, add(2, 3))").unwrap(), SunVariable::Number(6.0));
+        assert_eq!(sun.eval_str("let f = add; f(1, 1)").unwrap(), SunVariable::Number(2.0));
+        assert_eq!(sun.eval_str("add(1)").unwrap_err().message(), "Expected 2 arguments but got 1");
+        assert_eq!(sun.eval_str("add(1, \"x\")").unwrap_err().message(), "add expects numbers");
+
+        let constant = "Cannot assign to true as it is a constant";
+        assert_eq!(sun.register_native("true", None, |_| Ok(SunVariable::Nil)).unwrap_err().message(), constant);
+    }
+
+    #[test]
+    fn set_global_keeps_constants() {
+        let mut sun = Interpreter::new();
+        sun.set_global("answer", SunVariable::Number(42.0)).unwrap();
+        sun.set_global("answer", SunVariable::Number(43.0)).unwrap();
+        assert_eq!(sun.eval_str("answer = answer + 1; answer").unwrap(), SunVariable::Number(44.0));
+
+        sun.eval_str("const limit = 10;").unwrap();
+        for name in ["true", "limit"] {
+            let err = sun.set_global(name, SunVariable::Bool(false)).unwrap_err();
+            assert_eq!(err.message(), format!("Cannot assign to {} as it is a constant", name));
         }
-        NodeType::Program => {
-            if let Some(program) = astNode.as_program() {
-                return statements::eval_program(program, env);
-            } else {
-                errorN(&format!("{:#?}", astNode).to_string());
-                return SunVariable::new().set_value(EnumVariableType::NIL, "");
-            }
+        assert_eq!(sun.eval_str("true").unwrap(), SunVariable::Bool(true));
+        assert_eq!(sun.eval_str("true = 1;").unwrap_err().message(), "Cannot assign to true as it is a constant");
+        assert_eq!(sun.eval_str("limit").unwrap(), SunVariable::Number(10.0));
+    }
+
+    #[test]
+    fn objects() {
+        assert_eq!(show("let a = 1; let o = { a, b: 2 }; o"), "{ a: 1, b: 2 }");
+        assert_eq!(show("let o = { b: 1, a: 2, }; o.b = 3; o.c = 0; o[\"d\"] = 4; o"), "{ b: 3, a: 2, c: 0, d: 4 }");
+        assert_eq!(show("let o = { complex: { bar: true } }; o.complex.bar"), "true");
+        assert_eq!(show("let o = { x: 1 }; o[\"x\"]"), "1");
+        assert_eq!(run("let o = {}; o.missing"), SunVariable::Nil);
+        assert_eq!(show("let o = { n: 1 }; let p = o; p.n = 2; o.n"), "2");
+        assert_eq!(error("let o = {}; o.a.b = 1"), "Cannot access property b of NIL");
+    }
+
+    #[test]
+    fn arrays() {
+        assert_eq!(show("let a = [1, 2, 3]; a[0] = 9; a"), "[9, 2, 3]");
+        assert_eq!(show("let a = [1]; a[1] = 2; a"), "[1, 2]");
+        assert_eq!(show("let a = [1]; let b = a; b[0] = 2; a"), "[2]");
+        assert_eq!(run("[1, 2, 3].length"), SunVariable::Number(3.0));
+        assert_eq!(run("[1][5]"), SunVariable::Nil);
+        assert_eq!(error("[1][-1]"), "Array index cannot be negative, got -1");
+        assert_eq!(error("let a = [1]; a[-1] = 2"), "Array index cannot be negative, got -1");
+        assert_eq!(error("[1][0.5]"), "Array index must be an integer, got 0.5");
+        assert_eq!(error("let a = [1]; a[3] = 1"), "Index 3 out of bounds for array of length 1");
+        assert_eq!(error("let a = [1]; a.length = 3"), "Cannot assign to the length of an array");
+    }
+
+    #[test]
+    fn dropping_an_interpreter_leaves_other_interpreters_alone() {
+        let mut a = Interpreter::new();
+        a.eval_str("let k = 5; fn g() { return k; }").unwrap();
+        let mut b = Interpreter::new();
+        b.set_global("g", a.get_global("g").unwrap()).unwrap();
+        assert_eq!(b.eval_str("g()").unwrap(), SunVariable::Number(5.0));
+        drop(b);
+
+        assert_eq!(a.eval_str("k").unwrap(), SunVariable::Number(5.0));
+        assert_eq!(a.eval_str("g()").unwrap(), SunVariable::Number(5.0));
+        assert!(matches!(a.eval_str("println").unwrap(), SunVariable::Native(_)));
+    }
+
+    #[test]
+    fn functions_held_by_the_host_outlive_the_interpreter() {
+        let mut sun = Interpreter::new();
+        sun.eval_str("let k = 5; fn g() { return k; }").unwrap();
+        let g = sun.get_global("g").unwrap();
+        drop(sun);
+
+        let mut other = Interpreter::new();
+        other.set_global("g", g).unwrap();
+        assert_eq!(other.eval_str("g()").unwrap(), SunVariable::Number(5.0));
+    }
+
+    #[test]
+    fn closures_created_in_a_loop_are_freed() {
+        let before = live_scopes();
+        let mut sun = Interpreter::new();
+        sun.eval_str("let i = 0; while (i < 20000) { let big = [i]; let f = fn() { return big; }; i = i + 1; }").unwrap();
+        assert!(live_scopes() - before < 4096, "{} scopes still alive", live_scopes() - before);
+
+        drop(sun);
+        assert_eq!(live_scopes(), before);
+    }
+
+    /// Runs `source` on a thread with a 2 MiB native stack, the default
+    /// for spawned threads. Returns the printed result or error message.
+    fn eval_on_small_stack(source: String) -> Result<String, String> {
+        std::thread::Builder::new()
+            .stack_size(2 * 1024 * 1024)
+            .spawn(move || match Interpreter::new().eval_str(&source) {
+                Ok(value) => Ok(value.to_string()),
+                Err(err) => Err(err.message().to_string()),
+            })
+            .unwrap()
+            .join()
+            .expect("the interpreter overflowed its stack")
+    }
+
+    #[test]
+    fn deep_recursion_is_an_error_not_a_crash() {
+        let count = "fn f(n) { if (n == 0) { return 0; } return f(n - 1) + 1; }";
+        assert_eq!(eval_on_small_stack(format!("{} f(50)", count)), Ok("50".to_string()));
+        assert_eq!(eval_on_small_stack(format!("{} f(100000)", count)), Err("Maximum call depth exceeded".to_string()));
+
+        let nested = "fn g(n) { if (n > 0) { { { while (true) { return g(n - 1); } } } } return 0; } g(100000)";
+        assert_eq!(eval_on_small_stack(nested.to_string()), Err("Maximum call depth exceeded".to_string()));
+    }
+
+    #[test]
+    fn deeply_nested_values_are_an_error_not_a_crash() {
+        let too_deep = Err("Expression nested too deeply".to_string());
+        for depth in [10, 100, crate::parse::MAX_NESTING_DEPTH - 10, 3000] {
+            let array = format!("typeof {}1{}", "[".repeat(depth), "]".repeat(depth));
+            let result = eval_on_small_stack(array);
+            assert!(result == Ok("array".to_string()) || (depth > 10 && result == too_deep), "{:?}", result);
         }
-        _ => {
-            println!("{:#?}", astNode);
-            errorN(&format!("{:#?}", astNode).to_string());
-            return SunVariable::new().set_value(EnumVariableType::NIL, "");
+
+        // Deep recursion leaves little stack for evaluating the literal
+        let literal = format!("{}1{}", "[".repeat(40), "]".repeat(40));
+        for calls in (0..3000).step_by(150) {
+            let source = format!("fn f(n) {{ if (n == 0) {{ return typeof {}; }} return f(n - 1); }} f({})", literal, calls);
+            let result = eval_on_small_stack(source);
+            let call_depth = Err("Maximum call depth exceeded".to_string());
+            assert!(result == Ok("array".to_string()) || result == call_depth || result == too_deep, "{:?}", result);
         }
     }
-}
\ No newline at end of file
+}
@@ -1,4 +1,5 @@
 pub mod value;
 pub mod interpreter;
 pub mod environmment;
+pub mod builtins;
 pub mod eval;
\ No newline at end of file
@@ -1,118 +1,269 @@
 use core::fmt;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 
-#[derive(Debug, PartialEq, Clone)]
+use crate::ast::Function;
+use crate::environmment::Environmment;
+use crate::error::SunError;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum EnumVariableType {
     NIL, 
     NUMBER,
     STRING, 
     BOOLEAN, 
     FUNCTION, 
-    OBJECT
+    OBJECT,
+    ARRAY
 }
 
-#[derive(Debug, PartialEq, Clone)]
+/// Property bag of a Sun object. Keys keep the order they were first added in.
+#[derive(PartialEq, Clone)]
 pub struct Object {
     Object_data: HashMap<String, SunVariable>,
+    keys: Vec<String>,
 }
 
-#[derive(PartialEq, Clone)]
-pub struct SunVariable {
-    type_: EnumVariableType,
-    string_value: String,
-    number_value: f64,
-    bool_value: bool,
-    Object_value: Object
+/// A user-defined function together with the scope it was defined in.
+/// The scope usually holds the function too, forming an `Rc` cycle that
+/// `collect_cycles` breaks once nothing else refers to either.
+pub struct SunFunction {
+    pub(crate) declaration: Function,
+    pub(crate) closure: Environmment,
+}
+
+/// Signature of a host function callable from Sun code.
+pub type NativeFn = dyn Fn(&[SunVariable]) -> Result<SunVariable, SunError>;
+
+/// A Rust function exposed to Sun code. `arity` is the exact number of
+/// arguments it accepts, or `None` if it takes any number.
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: Option<usize>,
+    pub(crate) function: Box<NativeFn>,
+}
+
+/// A Sun runtime value. Strings, objects, arrays and functions are
+/// reference counted, so cloning a value never copies its contents and
+/// every copy of an object or array sees the same data.
+#[derive(Clone, Default)]
+pub enum SunVariable {
+    #[default]
+    Nil,
+    Number(f64),
+    Bool(bool),
+    String(Rc<str>),
+    Object(Rc<RefCell<Object>>),
+    Array(Rc<RefCell<Vec<SunVariable>>>),
+    Function(Rc<SunFunction>),
+    Native(Rc<NativeFunction>),
+}
+
+impl Default for Object {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Object {
     pub fn new() -> Self {
         Self {
-            Object_data: HashMap::new()
+            Object_data: HashMap::new(),
+            keys: Vec::new()
         }
     }
     pub fn add_variable(&mut self, name: String, variable: SunVariable) {
+        if !self.Object_data.contains_key(&name) {
+            self.keys.push(name.clone());
+        }
         self.Object_data.insert(name, variable);
     }
+
+    pub fn get_variable(&self, name: &str) -> Option<SunVariable> {
+        self.Object_data.get(name).cloned()
+    }
     
     pub fn remove_variable(&mut self, name: &str) {
-        self.Object_data.remove(name);
+        if self.Object_data.remove(name).is_some() {
+            self.keys.retain(|key| key != name);
+        }
+    }
+
+    /// Properties in insertion order.
+    pub fn entries(&self) -> impl Iterator<Item = (&String, &SunVariable)> {
+        self.keys.iter().map(move |key| (key, &self.Object_data[key]))
+    }
+}
+
+impl fmt::Debug for Object {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.entries()).finish()
+    }
+}
+
+thread_local! {
+    // Objects and arrays currently being formatted further up the stack
+    static FORMATTING: RefCell<Vec<*const ()>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Formats a container with `write`, or as `[Circular]` if it is already
+/// being formatted, so self-referencing values don't recurse forever.
+fn guard_cycle(ptr: *const (), f: &mut fmt::Formatter, write: impl FnOnce(&mut fmt::Formatter) -> fmt::Result) -> fmt::Result {
+    if FORMATTING.with(|formatting| formatting.borrow().contains(&ptr)) {
+        return write!(f, "[Circular]");
+    }
+    FORMATTING.with(|formatting| formatting.borrow_mut().push(ptr));
+    let result = write(f);
+    FORMATTING.with(|formatting| formatting.borrow_mut().pop());
+    result
+}
+
+impl PartialEq for SunVariable {
+    // Primitives compare by value; objects, arrays and functions by identity
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (SunVariable::Nil, SunVariable::Nil) => true,
+            (SunVariable::Number(left), SunVariable::Number(right)) => left == right,
+            (SunVariable::Bool(left), SunVariable::Bool(right)) => left == right,
+            (SunVariable::String(left), SunVariable::String(right)) => left == right,
+            (SunVariable::Object(left), SunVariable::Object(right)) => Rc::ptr_eq(left, right),
+            (SunVariable::Array(left), SunVariable::Array(right)) => Rc::ptr_eq(left, right),
+            (SunVariable::Function(left), SunVariable::Function(right)) => Rc::ptr_eq(left, right),
+            (SunVariable::Native(left), SunVariable::Native(right)) => Rc::ptr_eq(left, right),
+            _ => false,
+        }
     }
 }
 
 impl fmt::Debug for SunVariable {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self.type_ {
-            EnumVariableType::NIL => {
+        match self {
+            SunVariable::Nil => {
                 write!(f, "SunVariable [nil]")
             }
-            EnumVariableType::NUMBER => {
-                write!(f, "SunVariable Number: {}", self.number_value)
+            SunVariable::Number(value) => {
+                write!(f, "SunVariable Number: {}", value)
             }
-            EnumVariableType::STRING => {
-                write!(f, "SunVariable String: {}", self.string_value)
+            SunVariable::String(value) => {
+                write!(f, "SunVariable String: {}", value)
             }
-            EnumVariableType::BOOLEAN => {
-                write!(f, "SunVariable Boolean: {}", self.bool_value)
+            SunVariable::Bool(value) => {
+                write!(f, "SunVariable Boolean: {}", value)
             }
-            EnumVariableType::OBJECT => {
-                write!(f, "SunVariable Object: {:#?}", self.Object_value)
+            SunVariable::Object(object) => {
+                write!(f, "SunVariable Object: ")?;
+                guard_cycle(Rc::as_ptr(object) as *const (), f, |f| write!(f, "{:#?}", object.borrow()))
             }
-            _ => {
-                write!(f, "SunVariable [Invalid]")
+            SunVariable::Array(elements) => {
+                write!(f, "SunVariable Array: ")?;
+                guard_cycle(Rc::as_ptr(elements) as *const (), f, |f| write!(f, "{:#?}", elements.borrow()))
+            }
+            SunVariable::Native(native) => {
+                write!(f, "SunVariable Function: <native {}>", native.name)
+            }
+            SunVariable::Function(function) => {
+                match &function.declaration.name {
+                    Some(name) => write!(f, "SunVariable Function: {}", name),
+                    None => write!(f, "SunVariable Function: <anonymous>"),
+                }
             }
         }
     }
 }
 
-impl SunVariable {
-    pub fn new() -> Self {
-        Self {
-            type_: EnumVariableType::NIL,
-            string_value: String::new(),
-            number_value: 0.0,
-            bool_value: false,
-            Object_value: Object::new(),
+/// User-facing rendering: numbers without a trailing `.0`, strings
+/// unquoted at the top level and quoted inside objects and arrays.
+impl fmt::Display for SunVariable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SunVariable::String(value) => write!(f, "{}", value),
+            other => other.write_nested(f),
         }
     }
-    pub fn set_value(&mut self, new_type: EnumVariableType, value: impl Into<String>) -> SunVariable {
-        match new_type {
-            EnumVariableType::NUMBER => {
-                if let Ok(num) = value.into().parse::<f64>() {
-                    self.number_value = num;
+}
+
+impl SunVariable {
+    fn write_nested(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SunVariable::Nil => write!(f, "nil"),
+            SunVariable::Number(value) => write!(f, "{}", value),
+            SunVariable::Bool(value) => write!(f, "{}", value),
+            SunVariable::String(value) => write!(f, "{:?}", value),
+            SunVariable::Object(object) => guard_cycle(Rc::as_ptr(object) as *const (), f, |f| {
+                let object = object.borrow();
+                if object.keys.is_empty() {
+                    return write!(f, "{{}}");
                 }
-            },
-            EnumVariableType::STRING => {
-                self.string_value = value.into();
-            },
-            EnumVariableType::BOOLEAN => {
-                if let Ok(b) = value.into().parse::<bool>() {
-                    self.bool_value = b;
+                write!(f, "{{ ")?;
+                for (i, (key, value)) in object.entries().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", key)?;
+                    value.write_nested(f)?;
+                }
+                write!(f, " }}")
+            }),
+            SunVariable::Array(elements) => guard_cycle(Rc::as_ptr(elements) as *const (), f, |f| {
+                write!(f, "[")?;
+                for (i, element) in elements.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    element.write_nested(f)?;
                 }
+                write!(f, "]")
+            }),
+            SunVariable::Native(native) => write!(f, "<native fn {}>", native.name),
+            SunVariable::Function(function) => match &function.declaration.name {
+                Some(name) => write!(f, "<fn {}>", name),
+                None => write!(f, "<fn>"),
             },
-            _ => {}
         }
-        self.type_ = new_type;
-        return self.clone();
     }
 
-    pub fn get_type(&self) -> &EnumVariableType {
-        &self.type_
+    pub fn string(value: impl Into<Rc<str>>) -> Self {
+        SunVariable::String(value.into())
     }
 
-    pub fn get_string(&self) -> &str {
-        &self.string_value
+    pub fn object(object: Object) -> Self {
+        SunVariable::Object(Rc::new(RefCell::new(object)))
     }
 
-    pub fn get_number(&self) -> f64 {
-        self.number_value
+    pub fn array(elements: Vec<SunVariable>) -> Self {
+        SunVariable::Array(Rc::new(RefCell::new(elements)))
     }
 
-    pub fn get_bool(&self) -> bool {
-        self.bool_value
+    pub fn function(function: SunFunction) -> Self {
+        SunVariable::Function(Rc::new(function))
     }
-    
-    pub fn get_object(&mut self) -> &mut Object {
-        &mut self.Object_value
+
+    pub fn native(native: NativeFunction) -> Self {
+        SunVariable::Native(Rc::new(native))
     }
-}
\ No newline at end of file
+
+    pub fn get_type(&self) -> EnumVariableType {
+        match self {
+            SunVariable::Nil => EnumVariableType::NIL,
+            SunVariable::Number(_) => EnumVariableType::NUMBER,
+            SunVariable::Bool(_) => EnumVariableType::BOOLEAN,
+            SunVariable::String(_) => EnumVariableType::STRING,
+            SunVariable::Object(_) => EnumVariableType::OBJECT,
+            SunVariable::Array(_) => EnumVariableType::ARRAY,
+            SunVariable::Function(_) | SunVariable::Native(_) => EnumVariableType::FUNCTION,
+        }
+    }
+
+    /// Whether the value counts as true in a condition: `nil`, `false`,
+    /// `0`, `NaN` and the empty string are false, everything else is true.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            SunVariable::Nil => false,
+            SunVariable::Bool(value) => *value,
+            SunVariable::Number(value) => *value != 0.0 && !value.is_nan(),
+            SunVariable::String(value) => !value.is_empty(),
+            _ => true,
+        }
+    }
+}